@@ -1,11 +1,44 @@
+#include <algorithm>
+#include <memory>
 #include <sstream>
+#include <string>
+#include <vector>
 #include <cstdint>
+#include <cstdlib>
+#include <cstring>
 
 #include <assert.h>
-
+#include <fnmatch.h>
+#include <libintl.h>
+#include <regex.h>
+
+#include <apt-pkg/acquire.h>
+#include <apt-pkg/acquire-item.h>
+#include <apt-pkg/algorithms.h>
+#include <apt-pkg/aptconfiguration.h>
 #include <apt-pkg/cachefile.h>
+#ifndef YE_OLDE_APT
+#include <apt-pkg/cachefilter.h>
+#endif
+#include <apt-pkg/configuration.h>
+#include <apt-pkg/debfile.h>
+#include <apt-pkg/debindexfile.h>
+#include <apt-pkg/depcache.h>
+#include <apt-pkg/dirstream.h>
+#include <apt-pkg/error.h>
+#include <apt-pkg/fileutl.h>
+#include <apt-pkg/hashes.h>
+#ifndef YE_OLDE_APT
+#include <apt-pkg/install-progress.h>
+#endif
+#include <apt-pkg/macros.h>
+#include <apt-pkg/packagemanager.h>
 #include <apt-pkg/pkgcache.h>
 #include <apt-pkg/pkgrecords.h>
+#include <apt-pkg/sourcelist.h>
+#ifndef YE_OLDE_APT
+#include <apt-pkg/upgrade.h>
+#endif
 #include <apt-pkg/version.h>
 
 #include <apt-pkg/init.h>
@@ -21,12 +54,28 @@ struct PCache {
 
     // Owned by us.
     pkgRecords *records;
+
+    // Indexed by ID; filled in the first time anything is looked up by ID.
+    std::vector<pkgCache::Package *> packages_by_id;
+    std::vector<pkgCache::Version *> versions_by_id;
 };
 
 struct PPkgIterator {
     // Owned by us.
     pkgCache::PkgIterator iterator;
 
+    // Owned by us. If set, only these packages are walked, instead of the rest of the cache.
+    std::vector<pkgCache::PkgIterator> *selection;
+    size_t selection_pos;
+
+    // Borrow of "static" PCache.
+    PCache *cache;
+};
+
+struct PGrpIterator {
+    // Owned by us.
+    pkgCache::GrpIterator iterator;
+
     // Borrow of "static" PCache.
     PCache *cache;
 };
@@ -35,6 +84,13 @@ struct PVerIterator {
     // Owned by us.
     pkgCache::VerIterator iterator;
 
+    // If set, stop after this version, instead of walking the rest of the package's versions.
+    bool single;
+
+    // Owned by us. If set, only these versions are walked, instead of the package's.
+    std::vector<pkgCache::VerIterator> *selection;
+    size_t selection_pos;
+
     // Borrowed from PCache.
     pkgCache::PkgIterator *pkg;
 
@@ -53,6 +109,14 @@ struct PDepIterator {
     PCache *cache;
 };
 
+struct PPrvIterator {
+    // Owned by us.
+    pkgCache::PrvIterator iterator;
+
+    // Borrow of "static" PCache.
+    PCache *cache;
+};
+
 struct PVerFileIterator {
     // Owned by us.
     pkgCache::VerFileIterator iterator;
@@ -66,34 +130,185 @@ struct PPkgFileIterator {
     pkgCache::PkgFileIterator iterator;
 };
 
+struct PDescription {
+    std::string language;
+    std::string md5;
+    std::string short_desc;
+    std::string long_desc;
+};
+
 struct PVerFileParser {
     pkgRecords::Parser *parser;
 };
 
+struct PChange {
+    pkgCache::PkgIterator pkg;
+    int32_t action;
+    std::string current_version;
+    std::string new_version;
+};
+
+struct PTransaction {
+    // Owned by us.
+    pkgDepCache *dep_cache;
+
+    // Filled in by transaction_changes.
+    std::vector<PChange> changes;
+
+    // Filled in by transaction_upgrade.
+    std::vector<pkgCache::PkgIterator> held_back;
+
+    // Borrow of "static" PCache.
+    PCache *cache;
+};
+
+// Called for each step of transaction_commit, with the user_data it was given.
+typedef void (*commit_callback)(void *user_data, int32_t kind, const char *package,
+                                uint32_t done, uint32_t total, const char *message);
+
+struct PDebMember {
+    std::string name;
+    std::string link_target;
+    int32_t type;
+    uint32_t mode;
+    uint32_t uid;
+    uint32_t gid;
+    uint64_t size;
+    uint64_t mtime;
+};
+
+struct PDebFile {
+    // Owned by us.
+    FileFd *fd;
+
+    // Owned by us, borrows fd.
+    debDebFile *deb;
+
+    // The "control" member, terminated so pkgTagSection can scan it.
+    std::string control;
+
+    // Borrows control.
+    pkgTagSection section;
+
+    // Filled in by deb_file_list_data.
+    std::vector<PDebMember> members;
+};
+
+// Records every member of a tar it is shown, optionally capturing
+// the contents of one of them.
+class MemberStream : public pkgDirStream {
+    std::string wanted;
+
+public:
+    std::vector<PDebMember> members;
+    std::string data;
+    bool found = false;
+
+    explicit MemberStream(std::string wanted) : wanted(wanted) {}
+
+    bool DoItem(Item &item, int &fd) override;
+    bool Process(Item &item, const unsigned char *data,
+                 unsigned long long size, unsigned long long pos) override;
+};
+
+#ifndef YE_OLDE_APT
+
+// apt decides phasing from its configuration, so a Phasing is applied there, for as
+// long as this is around, and the previous settings put back after.
+class PhasingConfig {
+    struct Saved {
+        std::string name;
+        bool existed;
+        std::string value;
+    };
+    std::vector<Saved> saved;
+
+    void set(const char *name, const char *value) {
+        saved.push_back({name, _config->Exists(name), _config->Find(name)});
+        _config->Set(name, value);
+    }
+
+public:
+    PhasingConfig(const char *machine_id, bool include_all) {
+        if (machine_id != nullptr) {
+            set("APT::Machine-ID", machine_id);
+        }
+        if (include_all) {
+            set("APT::Get::Always-Include-Phased-Updates", "true");
+        }
+    }
+
+    ~PhasingConfig() {
+        for (const Saved &setting : saved) {
+            if (setting.existed) {
+                _config->Set(setting.name, setting.value);
+            } else {
+                _config->Clear(setting.name);
+            }
+        }
+    }
+};
+
+#endif
+
 extern "C" {
     void init_config_system();
 
-    PCache *pkg_cache_create();
+    PCache *pkg_cache_create(const char *const *deb_files, size_t deb_file_count);
     void pkg_cache_release(PCache *cache);
 
+    // config access and mutation; only call these with the cache locked
+    char *config_native_arch();
+    char *config_architectures();
+    void config_add_architectures(const char *const *archs, size_t count);
+    void config_set_root_dir(const char *root);
+    char *config_find(const char *name);
+    char *config_find_file(const char *name);
+
+    char *file_md5(const char *path);
+
     int32_t pkg_cache_compare_versions(PCache *cache, const char *left, const char *right);
 
     // pkg_iter creation and deletion
     PPkgIterator *pkg_cache_pkg_iter(PCache *cache);
     PPkgIterator *pkg_cache_find_name(PCache *cache, const char *name);
     PPkgIterator *pkg_cache_find_name_arch(PCache *cache, const char *name, const char *arch);
+    PPkgIterator *pkg_cache_find_glob(PCache *cache, const char *glob);
+    PPkgIterator *pkg_cache_find_regex(PCache *cache, const char *regex);
+    PPkgIterator *pkg_cache_search(PCache *cache, const char *regex, bool names_only, bool full);
+#ifndef YE_OLDE_APT
+    PPkgIterator *pkg_cache_select(PCache *cache, const char *pattern);
+#endif
     void pkg_iter_release(PPkgIterator *iterator);
 
     // pkg_iter mutation
     void pkg_iter_next(PPkgIterator *iterator);
     bool pkg_iter_end(PPkgIterator *iterator);
 
+    PPkgIterator *pkg_cache_find_package_id(PCache *cache, uint32_t id);
+    PVerIterator *pkg_cache_find_version_id(PCache *cache, uint32_t id);
+
     // pkg_iter access
+    uint32_t pkg_iter_id(PPkgIterator *iterator);
     const char *pkg_iter_name(PPkgIterator *iterator);
     const char *pkg_iter_arch(PPkgIterator *iterator);
     const char *pkg_iter_current_version(PPkgIterator *iterator);
     const char *pkg_iter_candidate_version(PPkgIterator *iterator);
+    bool pkg_iter_is_auto(PPkgIterator *iterator);
+
+
+    // grp_iter creation and deletion
+    PGrpIterator *pkg_cache_grp_iter(PCache *cache);
+    void grp_iter_release(PGrpIterator *iterator);
+
+    // grp_iter mutation
+    void grp_iter_next(PGrpIterator *iterator);
+    bool grp_iter_end(PGrpIterator *iterator);
 
+    // grp_iter access
+    const char *grp_iter_name(PGrpIterator *iterator);
+    PPkgIterator *grp_iter_pkg_iter(PGrpIterator *iterator);
+    PPkgIterator *grp_iter_find_preferred_pkg(PGrpIterator *iterator);
 
     // ver_iter creation and deletion
     PVerIterator *pkg_iter_ver_iter(PPkgIterator *iterator);
@@ -104,19 +319,49 @@ extern "C" {
     bool ver_iter_end(PVerIterator *iterator);
 
     // ver_iter access
+    uint32_t ver_iter_id(PVerIterator *iterator);
     const char *ver_iter_version(PVerIterator *iterator);
     const char *ver_iter_section(PVerIterator *iterator);
     const char *ver_iter_arch(PVerIterator *iterator);
     const char *ver_iter_priority_type(PVerIterator *wrapper);
+    uint8_t ver_iter_multi_arch(PVerIterator *iterator);
+    PPkgIterator *ver_iter_pkg(PVerIterator *iterator);
+
+#ifndef YE_OLDE_APT
+    char *ver_iter_changelog_uri(PVerIterator *iterator);
+#endif
+
+    uint64_t ver_iter_size(PVerIterator *iterator);
+    uint64_t ver_iter_installed_size(PVerIterator *iterator);
+    char *ver_iter_file_name(PVerIterator *iterator);
+    char *ver_iter_download_uri(PVerIterator *iterator);
+#ifndef YE_OLDE_APT
+    char *ver_iter_hashes(PVerIterator *iterator);
+#endif
+
+    bool fetch_file(const char *uri, const char *dest_dir, const char *dest_name);
+
+    // description creation and deletion
+    PDescription *ver_iter_translated_description(PVerIterator *iterator, const char *lang);
+    void description_release(PDescription *description);
+
+    // description access
+    char *description_language(PDescription *description);
+    char *description_md5(PDescription *description);
+    char *description_short_desc(PDescription *description);
+    char *description_long_desc(PDescription *description);
 
 #ifndef YE_OLDE_APT
     const char *ver_iter_source_package(PVerIterator *iterator);
     const char *ver_iter_source_version(PVerIterator *iterator);
     int32_t ver_iter_priority(PVerIterator *iterator);
+    uint8_t ver_iter_phased_update_percentage(PVerIterator *iterator);
+    bool ver_iter_phased_in(PVerIterator *iterator, const char *machine_id, bool include_all);
 #endif
 
     // dep_iter creation and deletion
     PDepIterator *ver_iter_dep_iter(PVerIterator *iterator);
+    PDepIterator *pkg_iter_rev_dep_iter(PPkgIterator *iterator);
     void dep_iter_release(PDepIterator *iterator);
 
     // dep_iter mutation
@@ -124,10 +369,29 @@ extern "C" {
     bool dep_iter_end(PDepIterator *iterator);
 
     // dep_iter access
+    uint32_t dep_iter_id(PDepIterator *iterator);
     PPkgIterator *dep_iter_target_pkg(PDepIterator *iterator);
     const char *dep_iter_target_ver(PDepIterator *iterator);
     const char *dep_iter_comp_type(PDepIterator *iterator);
     const char *dep_iter_dep_type(PDepIterator *iterator);
+    bool dep_iter_is_or(PDepIterator *iterator);
+    PVerIterator *dep_iter_all_targets(PDepIterator *iterator);
+    PVerIterator *dep_iter_parent_ver(PDepIterator *iterator);
+
+    // prv_iter creation and deletion
+    PPrvIterator *ver_iter_prv_iter(PVerIterator *iterator);
+    PPrvIterator *pkg_iter_prv_iter(PPkgIterator *iterator);
+    void prv_iter_release(PPrvIterator *iterator);
+
+    // prv_iter mutation
+    void prv_iter_next(PPrvIterator *iterator);
+    bool prv_iter_end(PPrvIterator *iterator);
+
+    // prv_iter access
+    PPkgIterator *prv_iter_target_pkg(PPrvIterator *iterator);
+    const char *prv_iter_provide_version(PPrvIterator *iterator);
+    PPkgIterator *prv_iter_owner_pkg(PPrvIterator *iterator);
+    const char *prv_iter_owner_version(PPrvIterator *iterator);
 
     // ver_file_iter creation and deletion
     PVerFileIterator *ver_iter_ver_file_iter(PVerIterator *iterator);
@@ -137,14 +401,16 @@ extern "C" {
     void ver_file_iter_next(PVerFileIterator *iterator);
     bool ver_file_iter_end(PVerFileIterator *iterator);
 
-    // ver_file_parser creation
+    // ver_file_parser creation and deletion
     PVerFileParser *ver_file_iter_get_parser(PVerFileIterator *iterator);
+    void ver_file_parser_release(PVerFileParser *parser);
 
     // ver_file_parser access
-    const char *ver_file_parser_short_desc(PVerFileParser *parser);
-    const char *ver_file_parser_long_desc(PVerFileParser *parser);
-    const char *ver_file_parser_maintainer(PVerFileParser *parser);
-    const char *ver_file_parser_homepage(PVerFileParser *parser);
+    char *ver_file_parser_short_desc(PVerFileParser *parser);
+    char *ver_file_parser_long_desc(PVerFileParser *parser);
+    char *ver_file_parser_maintainer(PVerFileParser *parser);
+    char *ver_file_parser_homepage(PVerFileParser *parser);
+    char *ver_file_parser_record(PVerFileParser *parser);
 
     // ver_file_iter has no accessors, only the creation of pkg_file_iter
 
@@ -168,6 +434,63 @@ extern "C" {
     const char *pkg_file_iter_component(PPkgFileIterator *iterator);
     const char *pkg_file_iter_architecture(PPkgFileIterator *iterator);
     const char *pkg_file_iter_index_type(PPkgFileIterator *iterator);
+
+    // errors, from the (thread local) apt error stack; popping also returns warnings
+    bool apt_error_pending();
+    char *apt_error_pop();
+
+    // transaction creation and deletion
+    PTransaction *transaction_create(PCache *cache);
+    void transaction_release(PTransaction *transaction);
+
+    // transaction mutation
+    bool transaction_set_candidate(PTransaction *transaction, const char *name, const char *version);
+    bool transaction_mark_install(PTransaction *transaction, const char *name);
+    bool transaction_mark_remove(PTransaction *transaction, const char *name, bool purge);
+    bool transaction_resolve(PTransaction *transaction);
+
+    // transaction access
+    uint32_t transaction_broken_count(PTransaction *transaction);
+    size_t transaction_changes(PTransaction *transaction);
+    const char *transaction_change_name(PTransaction *transaction, size_t idx);
+    const char *transaction_change_arch(PTransaction *transaction, size_t idx);
+    int32_t transaction_change_action(PTransaction *transaction, size_t idx);
+    const char *transaction_change_current_version(PTransaction *transaction, size_t idx);
+    const char *transaction_change_new_version(PTransaction *transaction, size_t idx);
+
+#ifndef YE_OLDE_APT
+    // transaction upgrades
+    bool transaction_upgrade(PTransaction *transaction, const char *machine_id, bool include_all);
+    size_t transaction_held_back(PTransaction *transaction);
+    const char *transaction_held_back_name(PTransaction *transaction, size_t idx);
+    const char *transaction_held_back_arch(PTransaction *transaction, size_t idx);
+
+    // transaction execution
+    int32_t transaction_commit(PTransaction *transaction, bool dry_run,
+                               commit_callback callback, void *user_data);
+#endif
+
+    // deb_file creation and deletion
+    PDebFile *deb_file_open(const char *path);
+    void deb_file_release(PDebFile *deb);
+
+    // deb_file control access
+    const char *deb_file_control(PDebFile *deb);
+    char *deb_file_control_field(PDebFile *deb, const char *name);
+    char *deb_file_control_member(PDebFile *deb, const char *name, size_t *len);
+
+    // deb_file data access
+    bool deb_file_list_data(PDebFile *deb);
+    size_t deb_file_data_count(PDebFile *deb);
+    const char *deb_file_data_name(PDebFile *deb, size_t idx);
+    const char *deb_file_data_link_target(PDebFile *deb, size_t idx);
+    int32_t deb_file_data_type(PDebFile *deb, size_t idx);
+    uint32_t deb_file_data_mode(PDebFile *deb, size_t idx);
+    uint32_t deb_file_data_uid(PDebFile *deb, size_t idx);
+    uint32_t deb_file_data_gid(PDebFile *deb, size_t idx);
+    uint64_t deb_file_data_size(PDebFile *deb, size_t idx);
+    uint64_t deb_file_data_mtime(PDebFile *deb, size_t idx);
+    char *deb_file_data_member(PDebFile *deb, const char *name, size_t *len);
 }
 
 void init_config_system() {
@@ -175,9 +498,33 @@ void init_config_system() {
     pkgInitSystem(*_config, _system);
 }
 
-PCache *pkg_cache_create() {
+PCache *pkg_cache_create(const char *const *deb_files, size_t deb_file_count) {
     pkgCacheFile *cache_file = new pkgCacheFile();
+
+    if (deb_file_count > 0) {
+        // like `apt install ./foo.deb`; these must be added before the cache is built
+        if (!cache_file->BuildSourceList()) {
+            delete cache_file;
+            return nullptr;
+        }
+
+        for (size_t i = 0; i < deb_file_count; ++i) {
+            // the (string) overload, which does these checks, only arrived in apt 1.9
+            if (!FileExists(deb_files[i]) || flExtension(deb_files[i]) != "deb") {
+                _error->Error("%s can't be added to the cache; it must be an existing .deb", deb_files[i]);
+                delete cache_file;
+                return nullptr;
+            }
+            cache_file->GetSourceList()->AddVolatileFile(new debDebPkgFileIndex(deb_files[i]));
+        }
+    }
+
     pkgCache *cache = cache_file->GetPkgCache();
+    if (cache == nullptr) {
+        delete cache_file;
+        return nullptr;
+    }
+
     pkgRecords *records = new pkgRecords(*cache);
 
     PCache *ret = new PCache();
@@ -195,6 +542,78 @@ void pkg_cache_release(PCache *cache) {
     delete cache;
 }
 
+char *config_native_arch() {
+    return strdup(_config->Find("APT::Architecture").c_str());
+}
+
+char *config_architectures() {
+    // native first, then any foreign ones (from APT::Architectures, or dpkg)
+    std::string ret;
+    for (const std::string &arch : APT::Configuration::getArchitectures()) {
+        if (!ret.empty()) {
+            ret += ',';
+        }
+        ret += arch;
+    }
+    return strdup(ret.c_str());
+}
+
+void config_add_architectures(const char *const *archs, size_t count) {
+    // what was configured before we started meddling, so rebuilds don't accumulate
+    static const std::vector<std::string> original = APT::Configuration::getArchitectures();
+
+    std::vector<std::string> wanted = original;
+    for (size_t i = 0; i < count; ++i) {
+        if (std::find(wanted.begin(), wanted.end(), archs[i]) == wanted.end()) {
+            wanted.push_back(archs[i]);
+        }
+    }
+
+    _config->Clear("APT::Architectures");
+    for (const std::string &arch : wanted) {
+        _config->Set("APT::Architectures::", arch);
+    }
+
+    // forget the cached answer, so the new list is used when the cache is built
+    APT::Configuration::getArchitectures(false);
+}
+
+void config_set_root_dir(const char *root) {
+    _config->Set("RootDir", root == nullptr ? "" : root);
+
+    // the system remembers where the status file is; this makes it look again
+    _system->Initialize(*_config);
+}
+
+char *config_find(const char *name) {
+    return strdup(_config->Find(name).c_str());
+}
+
+char *config_find_file(const char *name) {
+    return strdup(_config->FindFile(name).c_str());
+}
+
+char *file_md5(const char *path) {
+    FileFd fd;
+    if (!fd.Open(path, FileFd::ReadOnly)) {
+        return nullptr;
+    }
+
+    Hashes hashes(Hashes::MD5SUM);
+    if (!hashes.AddFD(fd)) {
+        return nullptr;
+    }
+
+    const HashStringList list = hashes.GetHashStringList();
+    const HashString *md5 = list.find("MD5Sum");
+    if (md5 == nullptr) {
+        _error->Error("couldn't calculate the md5 of %s", path);
+        return nullptr;
+    }
+
+    return strdup(md5->HashValue().c_str());
+}
+
 int32_t pkg_cache_compare_versions(PCache *cache, const char *left, const char *right) {
     // an int is returned here; presumably it will always be -1, 0 or 1.
     return cache->cache->VS->DoCmpVersion(left, left+strlen(left), right, right+strlen(right));
@@ -221,18 +640,204 @@ PPkgIterator *pkg_cache_find_name_arch(PCache *cache, const char *name, const ch
     return wrapper;
 }
 
+// Takes ownership of the selection.
+static PPkgIterator *pkg_iter_from_selection(PCache *cache, std::vector<pkgCache::PkgIterator> *selection) {
+    PPkgIterator *wrapper = new PPkgIterator();
+    wrapper->selection = selection;
+    wrapper->selection_pos = 0;
+    wrapper->iterator = selection->empty() ? cache->cache->PkgEnd() : selection->front();
+    wrapper->cache = cache;
+    return wrapper;
+}
+
+static void pkg_cache_index_ids(PCache *cache) {
+    if (!cache->packages_by_id.empty()) {
+        return;
+    }
+
+    pkgCache &c = *cache->cache;
+    cache->packages_by_id.resize(c.HeaderP->PackageCount);
+    cache->versions_by_id.resize(c.HeaderP->VersionCount);
+    for (pkgCache::PkgIterator pkg = c.PkgBegin(); !pkg.end(); ++pkg) {
+        cache->packages_by_id[pkg->ID] = pkg;
+        for (pkgCache::VerIterator ver = pkg.VersionList(); !ver.end(); ++ver) {
+            cache->versions_by_id[ver->ID] = ver;
+        }
+    }
+}
+
+PPkgIterator *pkg_cache_find_package_id(PCache *cache, uint32_t id) {
+    pkg_cache_index_ids(cache);
+
+    std::vector<pkgCache::PkgIterator> *selection = new std::vector<pkgCache::PkgIterator>();
+    if (id < cache->packages_by_id.size()) {
+        selection->push_back(pkgCache::PkgIterator(*cache->cache, cache->packages_by_id[id]));
+    }
+    return pkg_iter_from_selection(cache, selection);
+}
+
+PVerIterator *pkg_cache_find_version_id(PCache *cache, uint32_t id) {
+    pkg_cache_index_ids(cache);
+
+    PVerIterator *wrapper = new PVerIterator();
+    if (id < cache->versions_by_id.size()) {
+        wrapper->iterator = pkgCache::VerIterator(*cache->cache, cache->versions_by_id[id]);
+    }
+    wrapper->single = true;
+    wrapper->pkg = nullptr;
+    wrapper->cache = cache;
+    return wrapper;
+}
+
+PPkgIterator *pkg_cache_find_glob(PCache *cache, const char *glob) {
+    // as with CacheSetHelper, "glob:arch" is matched against the full name
+    bool with_arch = strchr(glob, ':') != nullptr;
+
+    auto *selection = new std::vector<pkgCache::PkgIterator>();
+    for (pkgCache::PkgIterator pkg = cache->cache->PkgBegin(); !pkg.end(); ++pkg) {
+        std::string name = with_arch ? pkg.FullName(false) : pkg.Name();
+        if (fnmatch(glob, name.c_str(), 0) == 0) {
+            selection->push_back(pkg);
+        }
+    }
+
+    return pkg_iter_from_selection(cache, selection);
+}
+
+static bool compile_regex(regex_t *re, const char *regex) {
+    // the flags apt-cache search uses
+    int err = regcomp(re, regex, REG_EXTENDED | REG_ICASE | REG_NOSUB);
+    if (err != 0) {
+        char buf[256];
+        regerror(err, re, buf, sizeof(buf));
+        _error->Error("Regex compilation error - %s", buf);
+        return false;
+    }
+    return true;
+}
+
+PPkgIterator *pkg_cache_find_regex(PCache *cache, const char *regex) {
+    regex_t re;
+    if (!compile_regex(&re, regex)) {
+        return nullptr;
+    }
+
+    auto *selection = new std::vector<pkgCache::PkgIterator>();
+    for (pkgCache::PkgIterator pkg = cache->cache->PkgBegin(); !pkg.end(); ++pkg) {
+        if (regexec(&re, pkg.Name(), 0, nullptr, 0) == 0) {
+            selection->push_back(pkg);
+        }
+    }
+
+    regfree(&re);
+    return pkg_iter_from_selection(cache, selection);
+}
+
+PPkgIterator *pkg_cache_search(PCache *cache, const char *regex, bool names_only, bool full) {
+    regex_t re;
+    if (!compile_regex(&re, regex)) {
+        return nullptr;
+    }
+
+    pkgPolicy *policy = cache->cache_file->GetPolicy();
+
+    auto *selection = new std::vector<pkgCache::PkgIterator>();
+    for (pkgCache::GrpIterator grp = cache->cache->GrpBegin(); !grp.end(); ++grp) {
+        // once per name, not per architecture, as apt-cache picks one
+        pkgCache::PkgIterator pkg = grp.FindPreferredPkg();
+        if (pkg.end()) {
+            continue;
+        }
+
+        // like apt-cache, only things which could be installed are interesting
+        pkgCache::VerIterator ver = policy->GetCandidateVer(pkg);
+        if (ver.end()) {
+            continue;
+        }
+
+        bool matched = regexec(&re, pkg.Name(), 0, nullptr, 0) == 0;
+
+        if (!matched && !names_only && full) {
+            const char *start;
+            const char *stop;
+            cache->records->Lookup(ver.FileList()).GetRec(start, stop);
+            std::string record(start, stop - start);
+            matched = regexec(&re, record.c_str(), 0, nullptr, 0) == 0;
+        }
+
+        // the record may only have the untranslated description, so --full looks here too
+        if (!matched && !names_only) {
+            pkgCache::DescIterator desc = ver.TranslatedDescription();
+            if (!desc.end()) {
+                pkgRecords::Parser &parser = cache->records->Lookup(desc.FileList());
+                std::string text = parser.ShortDesc() + "\n" + parser.LongDesc();
+                matched = regexec(&re, text.c_str(), 0, nullptr, 0) == 0;
+            }
+        }
+
+        if (matched) {
+            selection->push_back(pkg);
+        }
+    }
+
+    regfree(&re);
+    return pkg_iter_from_selection(cache, selection);
+}
+
+#ifndef YE_OLDE_APT
+
+PPkgIterator *pkg_cache_select(PCache *cache, const char *pattern) {
+#if APT_PKG_ABI >= 590
+    // errors look like "input:3-8: error: ...", followed by the pattern and some ^^^s
+    std::unique_ptr<APT::CacheFilter::Matcher> matcher =
+        APT::CacheFilter::ParsePattern(pattern, cache->cache_file);
+    if (!matcher) {
+        return nullptr;
+    }
+
+    auto *selection = new std::vector<pkgCache::PkgIterator>();
+    for (pkgCache::PkgIterator pkg = cache->cache->PkgBegin(); !pkg.end(); ++pkg) {
+        if ((*matcher)(pkg)) {
+            selection->push_back(pkg);
+        }
+    }
+
+    return pkg_iter_from_selection(cache, selection);
+#else
+    _error->Error("patterns need apt 1.9 or later");
+    return nullptr;
+#endif
+}
+
+#endif
+
 void pkg_iter_release(PPkgIterator *wrapper) {
+    delete wrapper->selection;
     delete wrapper;
 }
 
 void pkg_iter_next(PPkgIterator *wrapper) {
-    ++wrapper->iterator;
+    if (wrapper->selection == nullptr) {
+        ++wrapper->iterator;
+        return;
+    }
+
+    ++wrapper->selection_pos;
+    if (wrapper->selection_pos < wrapper->selection->size()) {
+        wrapper->iterator = (*wrapper->selection)[wrapper->selection_pos];
+    } else {
+        wrapper->iterator = wrapper->cache->cache->PkgEnd();
+    }
 }
 
 bool pkg_iter_end(PPkgIterator *wrapper) {
     return wrapper->cache->cache->PkgEnd() == wrapper->iterator;
 }
 
+uint32_t pkg_iter_id(PPkgIterator *wrapper) {
+    return wrapper->iterator->ID;
+}
+
 const char *pkg_iter_name(PPkgIterator *wrapper) {
     return wrapper->iterator.Name();
 }
@@ -253,6 +858,55 @@ const char *pkg_iter_candidate_version(PPkgIterator *wrapper) {
     return it.VerStr();
 }
 
+bool pkg_iter_is_auto(PPkgIterator *wrapper) {
+    // reads extended_states the first time
+    pkgDepCache *dep_cache = wrapper->cache->cache_file->GetDepCache();
+    if (dep_cache == nullptr) {
+        return false;
+    }
+    return ((*dep_cache)[wrapper->iterator].Flags & pkgCache::Flag::Auto) != 0;
+}
+
+PGrpIterator *pkg_cache_grp_iter(PCache *cache) {
+    PGrpIterator *wrapper = new PGrpIterator();
+    wrapper->iterator = cache->cache->GrpBegin();
+    wrapper->cache = cache;
+    return wrapper;
+}
+
+void grp_iter_release(PGrpIterator *wrapper) {
+    delete wrapper;
+}
+
+void grp_iter_next(PGrpIterator *wrapper) {
+    ++wrapper->iterator;
+}
+
+bool grp_iter_end(PGrpIterator *wrapper) {
+    return wrapper->iterator.end();
+}
+
+const char *grp_iter_name(PGrpIterator *wrapper) {
+    return wrapper->iterator.Name();
+}
+
+PPkgIterator *grp_iter_pkg_iter(PGrpIterator *wrapper) {
+    auto *selection = new std::vector<pkgCache::PkgIterator>();
+    for (pkgCache::PkgIterator pkg = wrapper->iterator.PackageList();
+         !pkg.end();
+         pkg = wrapper->iterator.NextPkg(pkg)) {
+        selection->push_back(pkg);
+    }
+    return pkg_iter_from_selection(wrapper->cache, selection);
+}
+
+PPkgIterator *grp_iter_find_preferred_pkg(PGrpIterator *wrapper) {
+    PPkgIterator *new_wrapper = new PPkgIterator();
+    new_wrapper->iterator = wrapper->iterator.FindPreferredPkg();
+    new_wrapper->cache = wrapper->cache;
+    return new_wrapper;
+}
+
 PVerIterator *pkg_iter_ver_iter(PPkgIterator *wrapper) {
     PVerIterator *new_wrapper = new PVerIterator();
     new_wrapper->iterator = wrapper->iterator.VersionList();
@@ -262,11 +916,24 @@ PVerIterator *pkg_iter_ver_iter(PPkgIterator *wrapper) {
 }
 
 void ver_iter_release(PVerIterator *wrapper) {
+    delete wrapper->selection;
     delete wrapper;
 }
 
 void ver_iter_next(PVerIterator *wrapper) {
-    ++wrapper->iterator;
+    if (wrapper->selection != nullptr) {
+        ++wrapper->selection_pos;
+        if (wrapper->selection_pos < wrapper->selection->size()) {
+            wrapper->iterator = (*wrapper->selection)[wrapper->selection_pos];
+        } else {
+            wrapper->iterator = pkgCache::VerIterator();
+        }
+    } else if (wrapper->single) {
+        // a default iterator is the end of any list
+        wrapper->iterator = pkgCache::VerIterator();
+    } else {
+        ++wrapper->iterator;
+    }
 }
 
 bool ver_iter_end(PVerIterator *wrapper) {
@@ -274,6 +941,10 @@ bool ver_iter_end(PVerIterator *wrapper) {
 }
 
 
+uint32_t ver_iter_id(PVerIterator *wrapper) {
+    return wrapper->iterator->ID;
+}
+
 const char *ver_iter_version(PVerIterator *wrapper) {
     return wrapper->iterator.VerStr();
 }
@@ -286,53 +957,265 @@ const char *ver_iter_priority_type(PVerIterator *wrapper) {
     return wrapper->iterator.PriorityType();
 }
 
-#ifndef YE_OLDE_APT
-
-const char *ver_iter_source_package(PVerIterator *wrapper) {
-    return wrapper->iterator.SourcePkgName();
+uint8_t ver_iter_multi_arch(PVerIterator *wrapper) {
+    // pkgCache::Version::VerMultiArch flags
+    return wrapper->iterator->MultiArch;
 }
 
-const char *ver_iter_source_version(PVerIterator *wrapper) {
-    return wrapper->iterator.SourceVerStr();
+PPkgIterator *ver_iter_pkg(PVerIterator *wrapper) {
+    PPkgIterator *new_wrapper = new PPkgIterator();
+    new_wrapper->iterator = wrapper->iterator.ParentPkg();
+    new_wrapper->cache = wrapper->cache;
+    return new_wrapper;
 }
 
-int32_t ver_iter_priority(PVerIterator *wrapper) {
-    // The priority is a "short", which is roughly a (signed) int16_t;
-    // going bigger just in case
-    return wrapper->cache->cache_file->GetPolicy()->GetPriority(wrapper->iterator);
+#ifndef YE_OLDE_APT
+char *ver_iter_changelog_uri(PVerIterator *wrapper) {
+    std::string uri = pkgAcqChangelog::URI(wrapper->iterator);
+    if (uri.empty()) {
+        return nullptr;
+    }
+    return strdup(uri.c_str());
 }
-
 #endif
 
-const char *ver_iter_arch(PVerIterator *wrapper) {
-    return wrapper->iterator.Arch();
-}
-
-
-PDepIterator *ver_iter_dep_iter(PVerIterator *wrapper) {
-    PDepIterator *new_wrapper = new PDepIterator();
-    new_wrapper->iterator = wrapper->iterator.DependsList();
-    new_wrapper->cache = wrapper->cache;
-    return new_wrapper;
+uint64_t ver_iter_size(PVerIterator *wrapper) {
+    return wrapper->iterator->Size;
 }
 
-void dep_iter_release(PDepIterator *wrapper) {
-    delete wrapper;
+uint64_t ver_iter_installed_size(PVerIterator *wrapper) {
+    return wrapper->iterator->InstalledSize;
 }
 
-void dep_iter_next(PDepIterator *wrapper) {
-    ++wrapper->iterator;
+// The first place this version can be downloaded from, i.e. not the status file.
+static bool ver_iter_archive_file(PVerIterator *wrapper, pkgCache::VerFileIterator &found) {
+    for (found = wrapper->iterator.FileList(); !found.end(); ++found) {
+        if ((found.File()->Flags & pkgCache::Flag::NotSource) == 0) {
+            return true;
+        }
+    }
+    return false;
 }
 
-bool dep_iter_end(PDepIterator *wrapper) {
-    return wrapper->iterator.end();
-}
+char *ver_iter_file_name(PVerIterator *wrapper) {
+    pkgCache::VerFileIterator file;
+    if (!ver_iter_archive_file(wrapper, file)) {
+        return nullptr;
+    }
 
-PPkgIterator *dep_iter_target_pkg(PDepIterator *wrapper) {
-    PPkgIterator *new_wrapper = new PPkgIterator();
-    new_wrapper->iterator = wrapper->iterator.TargetPkg();
-    new_wrapper->cache = wrapper->cache;
-    return new_wrapper;
+    std::string name = wrapper->cache->records->Lookup(file).FileName();
+    if (name.empty()) {
+        return nullptr;
+    }
+    return strdup(name.c_str());
+}
+
+char *ver_iter_download_uri(PVerIterator *wrapper) {
+    pkgCache::VerFileIterator file;
+    if (!ver_iter_archive_file(wrapper, file)) {
+        return nullptr;
+    }
+
+    pkgIndexFile *index;
+    if (!wrapper->cache->cache_file->GetSourceList()->FindIndex(file.File(), index)) {
+        return nullptr;
+    }
+
+    std::string name = wrapper->cache->records->Lookup(file).FileName();
+    std::string uri = index->ArchiveURI(name);
+    if (name.empty() || uri.empty()) {
+        return nullptr;
+    }
+    return strdup(uri.c_str());
+}
+
+#ifndef YE_OLDE_APT
+char *ver_iter_hashes(PVerIterator *wrapper) {
+    pkgCache::VerFileIterator file;
+    if (!ver_iter_archive_file(wrapper, file)) {
+        return strdup("");
+    }
+
+    // one `Type:value` per line
+    std::string ret;
+    const HashStringList hashes = wrapper->cache->records->Lookup(file).Hashes();
+    for (const HashString &hash : hashes) {
+        ret += hash.toStr();
+        ret += '\n';
+    }
+    return strdup(ret.c_str());
+}
+#endif
+
+bool fetch_file(const char *uri, const char *dest_dir, const char *dest_name) {
+    // the acquire system is chatty (e.g. about sandboxing); only keep that if it fails
+    _error->PushToStack();
+
+    pkgAcquire fetcher;
+
+    // owned by the fetcher
+    pkgAcqFile *item = new pkgAcqFile(&fetcher, uri, HashStringList(), 0, uri, flNotDir(uri), dest_dir, dest_name);
+
+    bool ok = fetcher.Run() == pkgAcquire::Continue;
+    if (ok && item->Status != pkgAcquire::Item::StatDone) {
+        _error->Error("couldn't fetch %s: %s", uri, item->ErrorText.c_str());
+        ok = false;
+    }
+
+    if (ok) {
+        _error->RevertToStack();
+    } else {
+        _error->MergeWithStack();
+    }
+    return ok;
+}
+
+PDescription *ver_iter_translated_description(PVerIterator *wrapper, const char *lang) {
+    pkgCache::DescIterator desc;
+    if (lang == nullptr) {
+        // whichever apt would show, from Acquire::Languages
+        desc = wrapper->iterator.TranslatedDescription();
+    } else {
+        for (desc = wrapper->iterator.DescriptionList(); !desc.end(); ++desc) {
+            if (strcmp(desc.LanguageCode(), lang) == 0) {
+                break;
+            }
+        }
+    }
+
+    if (desc.end()) {
+        return nullptr;
+    }
+
+    pkgRecords::Parser &parser = wrapper->cache->records->Lookup(desc.FileList());
+
+    PDescription *description = new PDescription();
+    description->language = desc.LanguageCode();
+    description->md5 = desc.md5();
+    description->short_desc = parser.ShortDesc(description->language);
+    description->long_desc = parser.LongDesc(description->language);
+    return description;
+}
+
+void description_release(PDescription *description) {
+    delete description;
+}
+
+char *description_language(PDescription *description) {
+    return strdup(description->language.c_str());
+}
+
+char *description_md5(PDescription *description) {
+    return strdup(description->md5.c_str());
+}
+
+char *description_short_desc(PDescription *description) {
+    return strdup(description->short_desc.c_str());
+}
+
+char *description_long_desc(PDescription *description) {
+    return strdup(description->long_desc.c_str());
+}
+
+#ifndef YE_OLDE_APT
+
+const char *ver_iter_source_package(PVerIterator *wrapper) {
+    return wrapper->iterator.SourcePkgName();
+}
+
+const char *ver_iter_source_version(PVerIterator *wrapper) {
+    return wrapper->iterator.SourceVerStr();
+}
+
+int32_t ver_iter_priority(PVerIterator *wrapper) {
+    // The priority is a "short", which is roughly a (signed) int16_t;
+    // going bigger just in case
+    return wrapper->cache->cache_file->GetPolicy()->GetPriority(wrapper->iterator);
+}
+
+// From the first of the version's records which has one; 100 if it's not being phased.
+static uint8_t phased_update_percentage(PCache *cache, pkgCache::VerIterator ver) {
+    for (pkgCache::VerFileIterator file = ver.FileList(); !file.end(); ++file) {
+        std::string field = cache->records->Lookup(file).RecordField("Phased-Update-Percentage");
+        if (!field.empty()) {
+            return std::min(std::max(atoi(field.c_str()), 0), 100);
+        }
+    }
+    return 100;
+}
+
+// Whether apt's phasing keeps back the upgrade to the package's candidate, as configured.
+static bool phasing_holds_back(pkgDepCache &dep_cache, pkgCache::PkgIterator pkg) {
+#ifdef HAVE_APT_PHASING
+    return dep_cache.PhasingApplies(pkg);
+#else
+    // this apt doesn't phase updates at all
+    (void) dep_cache;
+    (void) pkg;
+    return false;
+#endif
+}
+
+uint8_t ver_iter_phased_update_percentage(PVerIterator *wrapper) {
+    return phased_update_percentage(wrapper->cache, wrapper->iterator);
+}
+
+bool ver_iter_phased_in(PVerIterator *wrapper, const char *machine_id, bool include_all) {
+    pkgCache::VerIterator ver = wrapper->iterator;
+    pkgCache::PkgIterator pkg = ver.ParentPkg();
+    pkgDepCache *dep_cache = wrapper->cache->cache_file->GetDepCache();
+
+    // phasing only ever keeps back an upgrade to the candidate
+    if (dep_cache == nullptr || pkg.CurrentVer().end() || dep_cache->GetCandidateVersion(pkg) != ver) {
+        return true;
+    }
+
+    PhasingConfig config(machine_id, include_all);
+    return !phasing_holds_back(*dep_cache, pkg);
+}
+
+#endif
+
+const char *ver_iter_arch(PVerIterator *wrapper) {
+    return wrapper->iterator.Arch();
+}
+
+
+PDepIterator *ver_iter_dep_iter(PVerIterator *wrapper) {
+    PDepIterator *new_wrapper = new PDepIterator();
+    new_wrapper->iterator = wrapper->iterator.DependsList();
+    new_wrapper->cache = wrapper->cache;
+    return new_wrapper;
+}
+
+PDepIterator *pkg_iter_rev_dep_iter(PPkgIterator *wrapper) {
+    PDepIterator *new_wrapper = new PDepIterator();
+    new_wrapper->iterator = wrapper->iterator.RevDependsList();
+    new_wrapper->cache = wrapper->cache;
+    return new_wrapper;
+}
+
+void dep_iter_release(PDepIterator *wrapper) {
+    delete wrapper;
+}
+
+void dep_iter_next(PDepIterator *wrapper) {
+    ++wrapper->iterator;
+}
+
+bool dep_iter_end(PDepIterator *wrapper) {
+    return wrapper->iterator.end();
+}
+
+uint32_t dep_iter_id(PDepIterator *wrapper) {
+    return wrapper->iterator->ID;
+}
+
+PPkgIterator *dep_iter_target_pkg(PDepIterator *wrapper) {
+    PPkgIterator *new_wrapper = new PPkgIterator();
+    new_wrapper->iterator = wrapper->iterator.TargetPkg();
+    new_wrapper->cache = wrapper->cache;
+    return new_wrapper;
 }
 
 const char *dep_iter_target_ver(PDepIterator *wrapper) {
@@ -347,6 +1230,88 @@ const char *dep_iter_dep_type(PDepIterator *wrapper) {
     return wrapper->iterator.DepType();
 }
 
+bool dep_iter_is_or(PDepIterator *wrapper) {
+    return (wrapper->iterator->CompareOp & pkgCache::Dep::Or) == pkgCache::Dep::Or;
+}
+
+PVerIterator *dep_iter_parent_ver(PDepIterator *wrapper) {
+    PVerIterator *new_wrapper = new PVerIterator();
+    new_wrapper->iterator = wrapper->iterator.ParentVer();
+    new_wrapper->single = true;
+    new_wrapper->pkg = nullptr;
+    new_wrapper->cache = wrapper->cache;
+    return new_wrapper;
+}
+
+PVerIterator *dep_iter_all_targets(PDepIterator *wrapper) {
+    auto *selection = new std::vector<pkgCache::VerIterator>();
+
+    // a null terminated array, which we own
+    std::unique_ptr<pkgCache::Version *[]> targets(wrapper->iterator.AllTargets());
+    for (pkgCache::Version **target = targets.get(); *target != nullptr; ++target) {
+        selection->push_back(pkgCache::VerIterator(*wrapper->cache->cache, *target));
+    }
+
+    PVerIterator *new_wrapper = new PVerIterator();
+    if (!selection->empty()) {
+        new_wrapper->iterator = selection->front();
+    }
+    new_wrapper->selection = selection;
+    new_wrapper->selection_pos = 0;
+    new_wrapper->pkg = nullptr;
+    new_wrapper->cache = wrapper->cache;
+    return new_wrapper;
+}
+
+
+PPrvIterator *ver_iter_prv_iter(PVerIterator *wrapper) {
+    PPrvIterator *new_wrapper = new PPrvIterator();
+    new_wrapper->iterator = wrapper->iterator.ProvidesList();
+    new_wrapper->cache = wrapper->cache;
+    return new_wrapper;
+}
+
+PPrvIterator *pkg_iter_prv_iter(PPkgIterator *wrapper) {
+    PPrvIterator *new_wrapper = new PPrvIterator();
+    new_wrapper->iterator = wrapper->iterator.ProvidesList();
+    new_wrapper->cache = wrapper->cache;
+    return new_wrapper;
+}
+
+void prv_iter_release(PPrvIterator *wrapper) {
+    delete wrapper;
+}
+
+void prv_iter_next(PPrvIterator *wrapper) {
+    ++wrapper->iterator;
+}
+
+bool prv_iter_end(PPrvIterator *wrapper) {
+    return wrapper->iterator.end();
+}
+
+PPkgIterator *prv_iter_target_pkg(PPrvIterator *wrapper) {
+    PPkgIterator *new_wrapper = new PPkgIterator();
+    new_wrapper->iterator = wrapper->iterator.ParentPkg();
+    new_wrapper->cache = wrapper->cache;
+    return new_wrapper;
+}
+
+const char *prv_iter_provide_version(PPrvIterator *wrapper) {
+    return wrapper->iterator.ProvideVersion();
+}
+
+PPkgIterator *prv_iter_owner_pkg(PPrvIterator *wrapper) {
+    PPkgIterator *new_wrapper = new PPkgIterator();
+    new_wrapper->iterator = wrapper->iterator.OwnerPkg();
+    new_wrapper->cache = wrapper->cache;
+    return new_wrapper;
+}
+
+const char *prv_iter_owner_version(PPrvIterator *wrapper) {
+    return wrapper->iterator.OwnerVer().VerStr();
+}
+
 
 PVerFileIterator *ver_iter_ver_file_iter(PVerIterator *wrapper) {
     PVerFileIterator *new_wrapper = new PVerFileIterator();
@@ -369,32 +1334,41 @@ PVerFileParser *ver_file_iter_get_parser(PVerFileIterator *wrapper) {
     return parser;
 }
 
-const char *to_c_string(std::string s) {
-    char *cstr = new char[s.length()+1];
-    std::strcpy(cstr, s.c_str());
-    return cstr;
+void ver_file_parser_release(PVerFileParser *parser) {
+    delete parser;
 }
 
-const char *ver_file_parser_short_desc(PVerFileParser *parser) {
+char *to_c_string(std::string s) {
+    return strdup(s.c_str());
+}
+
+char *ver_file_parser_short_desc(PVerFileParser *parser) {
     std::string desc = parser->parser->ShortDesc();
     return to_c_string(desc);
 }
 
-const char *ver_file_parser_long_desc(PVerFileParser *parser) {
+char *ver_file_parser_long_desc(PVerFileParser *parser) {
     std::string desc = parser->parser->LongDesc();
     return to_c_string(desc);
 }
 
-const char *ver_file_parser_maintainer(PVerFileParser *parser) {
+char *ver_file_parser_maintainer(PVerFileParser *parser) {
     std::string maint = parser->parser->Maintainer();
     return to_c_string(maint);
 }
 
-const char *ver_file_parser_homepage(PVerFileParser *parser) {
+char *ver_file_parser_homepage(PVerFileParser *parser) {
     std::string hp = parser->parser->Homepage();
     return to_c_string(hp);
 }
 
+char *ver_file_parser_record(PVerFileParser *parser) {
+    const char *start;
+    const char *stop;
+    parser->parser->GetRec(start, stop);
+    return strndup(start, stop - start);
+}
+
 bool ver_file_iter_end(PVerFileIterator *wrapper) {
     return wrapper->iterator.end();
 }
@@ -456,3 +1430,570 @@ const char *pkg_file_iter_architecture(PPkgFileIterator *wrapper) {
 const char *pkg_file_iter_index_type(PPkgFileIterator *wrapper) {
     return wrapper->iterator.IndexType();
 }
+
+bool apt_error_pending() {
+    return _error->PendingError();
+}
+
+char *apt_error_pop() {
+    if (_error->empty()) {
+        return nullptr;
+    }
+    std::string msg;
+    _error->PopMessage(msg);
+    return strdup(msg.c_str());
+}
+
+// tar members are usually "./usr/bin/foo"; accept "usr/bin/foo" and "/usr/bin/foo" too
+static std::string normalise_member_name(const char *name) {
+    std::string ret = name;
+    if (ret.compare(0, 2, "./") == 0) {
+        ret.erase(0, 2);
+    }
+    while (!ret.empty() && ret[0] == '/') {
+        ret.erase(0, 1);
+    }
+    return ret;
+}
+
+bool MemberStream::DoItem(Item &item, int &fd) {
+    PDebMember member;
+    member.name = item.Name;
+    member.link_target = item.LinkTarget == nullptr ? "" : item.LinkTarget;
+    member.type = item.Type;
+    member.mode = item.Mode;
+    member.uid = item.UID;
+    member.gid = item.GID;
+    member.size = item.Size;
+    member.mtime = item.MTime;
+    members.push_back(member);
+
+    if (!wanted.empty() && !found && normalise_member_name(item.Name) == wanted) {
+        found = true;
+        // -2 asks ExtractTar to hand the contents to Process, instead of writing to disc
+        fd = -2;
+    } else {
+        fd = -1;
+    }
+    return true;
+}
+
+bool MemberStream::Process(Item &, const unsigned char *data,
+                           unsigned long long size, unsigned long long) {
+    this->data.append(reinterpret_cast<const char *>(data), size);
+    return true;
+}
+
+static char *to_malloc_buffer(const std::string &s, size_t *len) {
+    char *buf = static_cast<char *>(malloc(s.length() + 1));
+    memcpy(buf, s.data(), s.length());
+    buf[s.length()] = '\0';
+    *len = s.length();
+    return buf;
+}
+
+PDebFile *deb_file_open(const char *path) {
+    FileFd *fd = new FileFd(path, FileFd::ReadOnly);
+    if (!fd->IsOpen()) {
+        delete fd;
+        return nullptr;
+    }
+
+    debDebFile *deb = new debDebFile(*fd);
+    if (_error->PendingError()) {
+        delete deb;
+        delete fd;
+        return nullptr;
+    }
+
+    MemberStream stream("control");
+    if (!deb->ExtractTarMember(stream, "control.tar") || !stream.found) {
+        if (!stream.found) {
+            _error->Error("%s has no control file", path);
+        }
+        delete deb;
+        delete fd;
+        return nullptr;
+    }
+
+    PDebFile *ret = new PDebFile();
+    ret->fd = fd;
+    ret->deb = deb;
+    ret->control = stream.data;
+    if (ret->control.empty() || ret->control.back() != '\n') {
+        ret->control += '\n';
+    }
+
+    if (!ret->section.Scan(ret->control.c_str(), ret->control.length())) {
+        _error->Error("%s has an unparsable control file", path);
+        deb_file_release(ret);
+        return nullptr;
+    }
+
+    return ret;
+}
+
+void deb_file_release(PDebFile *deb) {
+    delete deb->deb;
+    delete deb->fd;
+    delete deb;
+}
+
+const char *deb_file_control(PDebFile *deb) {
+    return deb->control.c_str();
+}
+
+char *deb_file_control_field(PDebFile *deb, const char *name) {
+    const char *start;
+    const char *stop;
+    if (!deb->section.Find(name, start, stop)) {
+        return nullptr;
+    }
+    return strndup(start, stop - start);
+}
+
+char *deb_file_control_member(PDebFile *deb, const char *name, size_t *len) {
+    MemberStream stream(normalise_member_name(name));
+    if (!deb->deb->ExtractTarMember(stream, "control.tar") || !stream.found) {
+        return nullptr;
+    }
+    return to_malloc_buffer(stream.data, len);
+}
+
+bool deb_file_list_data(PDebFile *deb) {
+    MemberStream stream("");
+    if (!deb->deb->ExtractArchive(stream)) {
+        return false;
+    }
+    deb->members = stream.members;
+    return true;
+}
+
+size_t deb_file_data_count(PDebFile *deb) {
+    return deb->members.size();
+}
+
+const char *deb_file_data_name(PDebFile *deb, size_t idx) {
+    return deb->members.at(idx).name.c_str();
+}
+
+const char *deb_file_data_link_target(PDebFile *deb, size_t idx) {
+    const std::string &target = deb->members.at(idx).link_target;
+    return target.empty() ? nullptr : target.c_str();
+}
+
+int32_t deb_file_data_type(PDebFile *deb, size_t idx) {
+    return deb->members.at(idx).type;
+}
+
+uint32_t deb_file_data_mode(PDebFile *deb, size_t idx) {
+    return deb->members.at(idx).mode;
+}
+
+uint32_t deb_file_data_uid(PDebFile *deb, size_t idx) {
+    return deb->members.at(idx).uid;
+}
+
+uint32_t deb_file_data_gid(PDebFile *deb, size_t idx) {
+    return deb->members.at(idx).gid;
+}
+
+uint64_t deb_file_data_size(PDebFile *deb, size_t idx) {
+    return deb->members.at(idx).size;
+}
+
+uint64_t deb_file_data_mtime(PDebFile *deb, size_t idx) {
+    return deb->members.at(idx).mtime;
+}
+
+char *deb_file_data_member(PDebFile *deb, const char *name, size_t *len) {
+    MemberStream stream(normalise_member_name(name));
+    if (!deb->deb->ExtractArchive(stream) || !stream.found) {
+        return nullptr;
+    }
+    return to_malloc_buffer(stream.data, len);
+}
+
+static pkgCache::PkgIterator find_pkg(PCache *cache, const char *name) {
+    // FindPkg understands "name:arch", too
+    return cache->cache->FindPkg(name);
+}
+
+PTransaction *transaction_create(PCache *cache) {
+    // a private depcache, so nothing done here leaks into the shared cache_file
+    pkgDepCache *dep_cache = new pkgDepCache(cache->cache, cache->cache_file->GetPolicy());
+    if (!dep_cache->Init(nullptr)) {
+        delete dep_cache;
+        return nullptr;
+    }
+
+    PTransaction *ret = new PTransaction();
+    ret->dep_cache = dep_cache;
+    ret->cache = cache;
+    return ret;
+}
+
+void transaction_release(PTransaction *transaction) {
+    delete transaction->dep_cache;
+    delete transaction;
+}
+
+bool transaction_set_candidate(PTransaction *transaction, const char *name, const char *version) {
+    pkgCache::PkgIterator pkg = find_pkg(transaction->cache, name);
+    if (pkg.end()) {
+        return false;
+    }
+
+    for (pkgCache::VerIterator ver = pkg.VersionList(); !ver.end(); ++ver) {
+        if (strcmp(ver.VerStr(), version) == 0) {
+            transaction->dep_cache->SetCandidateVersion(ver);
+            return true;
+        }
+    }
+
+    return false;
+}
+
+bool transaction_mark_install(PTransaction *transaction, const char *name) {
+    pkgCache::PkgIterator pkg = find_pkg(transaction->cache, name);
+    if (pkg.end()) {
+        return false;
+    }
+
+    pkgDepCache::ActionGroup group(*transaction->dep_cache);
+    transaction->dep_cache->MarkInstall(pkg, true);
+    transaction->dep_cache->MarkAuto(pkg, false);
+    return true;
+}
+
+bool transaction_mark_remove(PTransaction *transaction, const char *name, bool purge) {
+    pkgCache::PkgIterator pkg = find_pkg(transaction->cache, name);
+    if (pkg.end()) {
+        return false;
+    }
+
+    pkgDepCache::ActionGroup group(*transaction->dep_cache);
+    transaction->dep_cache->MarkDelete(pkg, purge);
+    return true;
+}
+
+bool transaction_resolve(PTransaction *transaction) {
+    pkgProblemResolver fix(transaction->dep_cache);
+    return fix.Resolve(true);
+}
+
+uint32_t transaction_broken_count(PTransaction *transaction) {
+    return transaction->dep_cache->BrokenCount();
+}
+
+// must match transaction::Action
+enum ChangeAction {
+    CHANGE_INSTALL = 0,
+    CHANGE_UPGRADE = 1,
+    CHANGE_DOWNGRADE = 2,
+    CHANGE_REINSTALL = 3,
+    CHANGE_REMOVE = 4,
+    CHANGE_PURGE = 5,
+};
+
+size_t transaction_changes(PTransaction *transaction) {
+    pkgDepCache &dep_cache = *transaction->dep_cache;
+    transaction->changes.clear();
+
+    for (pkgCache::PkgIterator pkg = transaction->cache->cache->PkgBegin(); !pkg.end(); ++pkg) {
+        pkgDepCache::StateCache &state = dep_cache[pkg];
+
+        PChange change;
+        change.pkg = pkg;
+        if (state.NewInstall()) {
+            change.action = CHANGE_INSTALL;
+        } else if (state.Upgrade()) {
+            change.action = CHANGE_UPGRADE;
+        } else if (state.Downgrade()) {
+            change.action = CHANGE_DOWNGRADE;
+        } else if ((state.iFlags & pkgDepCache::ReInstall) != 0) {
+            change.action = CHANGE_REINSTALL;
+        } else if (state.Delete()) {
+            change.action = (state.iFlags & pkgDepCache::Purge) != 0 ? CHANGE_PURGE : CHANGE_REMOVE;
+        } else {
+            continue;
+        }
+
+        if (!pkg.CurrentVer().end()) {
+            change.current_version = pkg.CurrentVer().VerStr();
+        }
+
+        pkgCache::VerIterator install = state.InstVerIter(dep_cache);
+        if (!state.Delete() && !install.end()) {
+            change.new_version = install.VerStr();
+        }
+
+        transaction->changes.push_back(change);
+    }
+
+    return transaction->changes.size();
+}
+
+const char *transaction_change_name(PTransaction *transaction, size_t idx) {
+    return transaction->changes.at(idx).pkg.Name();
+}
+
+const char *transaction_change_arch(PTransaction *transaction, size_t idx) {
+    return transaction->changes.at(idx).pkg.Arch();
+}
+
+int32_t transaction_change_action(PTransaction *transaction, size_t idx) {
+    return transaction->changes.at(idx).action;
+}
+
+const char *transaction_change_current_version(PTransaction *transaction, size_t idx) {
+    const std::string &ver = transaction->changes.at(idx).current_version;
+    return ver.empty() ? nullptr : ver.c_str();
+}
+
+const char *transaction_change_new_version(PTransaction *transaction, size_t idx) {
+    const std::string &ver = transaction->changes.at(idx).new_version;
+    return ver.empty() ? nullptr : ver.c_str();
+}
+
+#ifndef YE_OLDE_APT
+
+// As `apt upgrade` does it, with apt's own phasing, for whichever machine it's told about.
+bool transaction_upgrade(PTransaction *transaction, const char *machine_id, bool include_all) {
+    pkgDepCache &dep_cache = *transaction->dep_cache;
+    transaction->held_back.clear();
+
+    PhasingConfig config(machine_id, include_all);
+    int mode = APT::Upgrade::FORBID_REMOVE_PACKAGES | APT::Upgrade::FORBID_INSTALL_NEW_PACKAGES;
+    if (!APT::Upgrade::Upgrade(dep_cache, mode, nullptr)) {
+        return false;
+    }
+
+    for (pkgCache::PkgIterator pkg = transaction->cache->cache->PkgBegin(); !pkg.end(); ++pkg) {
+        if (!dep_cache[pkg].Upgrade() && phasing_holds_back(dep_cache, pkg)) {
+            transaction->held_back.push_back(pkg);
+        }
+    }
+
+    return true;
+}
+
+size_t transaction_held_back(PTransaction *transaction) {
+    return transaction->held_back.size();
+}
+
+const char *transaction_held_back_name(PTransaction *transaction, size_t idx) {
+    return transaction->held_back.at(idx).Name();
+}
+
+const char *transaction_held_back_arch(PTransaction *transaction, size_t idx) {
+    return transaction->held_back.at(idx).Arch();
+}
+
+// must match transaction::EventKind
+enum CommitEvent {
+    EVENT_UNPACK = 0,
+    EVENT_CONFIGURE = 1,
+    EVENT_REMOVE = 2,
+    EVENT_TRIGGER = 3,
+    EVENT_ERROR = 4,
+    EVENT_CONFFILE_PROMPT = 5,
+    EVENT_OTHER = 6,
+};
+
+// must match transaction::Transaction::commit
+enum CommitResult {
+    COMMIT_NOT_STARTED = -1,
+    COMMIT_COMPLETED = 0,
+    COMMIT_FAILED = 1,
+    COMMIT_INCOMPLETE = 2,
+};
+
+// pkgDPkgPM only reports the (translated) text for each step, so match it against the
+// texts it uses. Longer prefixes first, as they all start the same way.
+static int32_t commit_event_kind(const std::string &action) {
+    static const struct {
+        const char *format;
+        int32_t kind;
+    } steps[] = {
+        {"Preparing to configure %s", EVENT_CONFIGURE},
+        {"Preparing for removal of %s", EVENT_REMOVE},
+        {"Preparing to completely remove %s", EVENT_REMOVE},
+        {"Preparing %s", EVENT_UNPACK},
+        {"Unpacking %s", EVENT_UNPACK},
+        {"Configuring %s", EVENT_CONFIGURE},
+        {"Installed %s", EVENT_CONFIGURE},
+        {"Removing %s", EVENT_REMOVE},
+        {"Removed %s", EVENT_REMOVE},
+        {"Completely removed %s", EVENT_REMOVE},
+        {"Noting disappearance of %s", EVENT_REMOVE},
+        {"Running post-installation trigger %s", EVENT_TRIGGER},
+        {"Processing triggers for %s", EVENT_TRIGGER},
+    };
+
+    for (const auto &step : steps) {
+        for (const char *format : {step.format, (const char *) dgettext("apt", step.format)}) {
+            const char *arg = strstr(format, "%s");
+            size_t len = arg == nullptr ? strlen(format) : arg - format;
+            if (action.compare(0, len, format, len) == 0) {
+                return step.kind;
+            }
+        }
+    }
+
+    return EVENT_OTHER;
+}
+
+class PCommitProgress : public APT::Progress::PackageManager {
+    commit_callback callback;
+    void *user_data;
+
+public:
+    uint32_t done = 0;
+    uint32_t total = 0;
+
+    PCommitProgress(commit_callback callback, void *user_data)
+        : callback(callback), user_data(user_data) {}
+
+    void emit(int32_t kind, const std::string &package, const std::string &message) {
+        callback(user_data, kind, package.c_str(), done, total, message.c_str());
+    }
+
+    virtual bool StatusChanged(std::string package, unsigned int steps_done,
+                               unsigned int total_steps, std::string action) override {
+        done = steps_done;
+        total = total_steps;
+        emit(commit_event_kind(action), package, action);
+        return true;
+    }
+
+    virtual void Error(std::string package, unsigned int steps_done,
+                       unsigned int total_steps, std::string message) override {
+        done = steps_done;
+        total = total_steps;
+        emit(EVENT_ERROR, package, message);
+    }
+
+    virtual void ConffilePrompt(std::string package, unsigned int steps_done,
+                                unsigned int total_steps, std::string message) override {
+        done = steps_done;
+        total = total_steps;
+        emit(EVENT_CONFFILE_PROMPT, package, message);
+    }
+};
+
+// Steps through the plan in the order dpkg would be run, without running it. pkgSimulate
+// would do the same, but prints every step on stdout, like apt-get --simulate does, so
+// these are only reported as events.
+class PSimulate : public pkgPackageManager {
+    PCommitProgress *progress;
+
+    void step(int32_t kind, pkgCache::PkgIterator pkg, const char *verb) {
+        progress->done++;
+        std::string name = pkg.FullName(false);
+        progress->emit(kind, name, std::string(verb) + " " + name);
+    }
+
+public:
+    PSimulate(pkgDepCache *dep_cache, PCommitProgress *progress)
+        : pkgPackageManager(dep_cache), progress(progress) {}
+
+protected:
+    virtual bool Install(pkgCache::PkgIterator pkg, std::string file) override {
+        step(EVENT_UNPACK, pkg, "Inst");
+        return true;
+    }
+
+    virtual bool Configure(pkgCache::PkgIterator pkg) override {
+        step(EVENT_CONFIGURE, pkg, "Conf");
+        return true;
+    }
+
+    virtual bool Remove(pkgCache::PkgIterator pkg, bool purge) override {
+        step(EVENT_REMOVE, pkg, purge ? "Purg" : "Remv");
+        return true;
+    }
+};
+
+static int32_t commit_result(pkgPackageManager::OrderResult result) {
+    switch (result) {
+        case pkgPackageManager::Completed:
+            return COMMIT_COMPLETED;
+        case pkgPackageManager::Incomplete:
+            return COMMIT_INCOMPLETE;
+        default:
+            return COMMIT_FAILED;
+    }
+}
+
+static int32_t commit_dry_run(PTransaction *transaction, PCommitProgress &progress) {
+    pkgDepCache &dep_cache = *transaction->dep_cache;
+
+    // an unpack and a configure for everything installed, and a removal for the rest
+    for (pkgCache::PkgIterator pkg = transaction->cache->cache->PkgBegin(); !pkg.end(); ++pkg) {
+        pkgDepCache::StateCache &state = dep_cache[pkg];
+        if (state.Delete()) {
+            progress.total += 1;
+        } else if (state.Install() || (state.iFlags & pkgDepCache::ReInstall) != 0) {
+            progress.total += 2;
+        }
+    }
+
+    PSimulate sim(&dep_cache, &progress);
+    return commit_result(sim.DoInstall(&progress));
+}
+
+// As apt-get does it: download everything, then hand over to dpkg.
+static int32_t commit_apply(PTransaction *transaction, PCommitProgress &progress) {
+    pkgDepCache &dep_cache = *transaction->dep_cache;
+
+    pkgSourceList sources;
+    if (!sources.ReadMainList() || !_system->Lock()) {
+        return COMMIT_NOT_STARTED;
+    }
+
+    std::unique_ptr<pkgPackageManager> pm(_system->CreatePM(&dep_cache));
+    {
+        pkgAcquire fetcher;
+        if (!fetcher.GetLock(_config->FindDir("Dir::Cache::Archives"))
+                || !pm->GetArchives(&fetcher, &sources, transaction->cache->records)
+                || fetcher.Run() != pkgAcquire::Continue) {
+            _system->UnLock();
+            return COMMIT_NOT_STARTED;
+        }
+
+        for (pkgAcquire::ItemIterator item = fetcher.ItemsBegin(); item != fetcher.ItemsEnd(); ++item) {
+            if ((*item)->Status != pkgAcquire::Item::StatDone) {
+                _error->Error("couldn't fetch %s: %s", (*item)->DescURI().c_str(),
+                              (*item)->ErrorText.c_str());
+                _system->UnLock();
+                return COMMIT_NOT_STARTED;
+            }
+        }
+    }
+
+    // dpkg takes its own lock, so only the frontend lock is kept, where there is one
+#if APT_PKG_ABI >= 590
+    _system->UnLockInner();
+#else
+    _system->UnLock();
+#endif
+    pkgPackageManager::OrderResult result = pm->DoInstall(&progress);
+#if APT_PKG_ABI >= 590
+    _system->UnLock();
+#endif
+    return commit_result(result);
+}
+
+int32_t transaction_commit(PTransaction *transaction, bool dry_run,
+                           commit_callback callback, void *user_data) {
+    if (transaction->dep_cache->BrokenCount() != 0) {
+        _error->Error("the transaction has broken packages");
+        return COMMIT_NOT_STARTED;
+    }
+
+    PCommitProgress progress(callback, user_data);
+    return dry_run ? commit_dry_run(transaction, progress) : commit_apply(transaction, progress);
+}
+
+#endif
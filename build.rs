@@ -1,5 +1,9 @@
 extern crate cc;
 
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
 const SRC: &str = "apt-pkg-c/lib.cpp";
 
 fn main() {
@@ -15,7 +19,68 @@ fn main() {
         build.define("YE_OLDE_APT", "1");
     }
 
+    // apt's own phasing decisions, as `apt upgrade` makes them; older apt doesn't phase
+    if has_phasing() {
+        build.define("HAVE_APT_PHASING", "1");
+    }
+
     build.compile("libapt-pkg-c.a");
 
     println!("cargo:rustc-link-lib=apt-pkg");
+
+    // debDebFile and friends were in their own library until apt 1.9 (libapt-pkg 5.90)
+    if needs_apt_inst() {
+        println!("cargo:rustc-link-lib=apt-inst");
+    }
+}
+
+/// Whether the installed `libapt-pkg` predates 1.9, according to its headers. Headers old
+/// enough to not define `APT_PKG_ABI` at all count as before, as `#if` treats it as zero.
+fn needs_apt_inst() -> bool {
+    let probe = match env::var("OUT_DIR") {
+        Ok(out_dir) => PathBuf::from(out_dir).join("apt-pkg-abi.cpp"),
+        Err(_) => return false,
+    };
+    let source = "#include <apt-pkg/macros.h>\n\
+                  #if APT_PKG_ABI < 590\n\
+                  apt_inst_is_separate\n\
+                  #endif\n";
+    if fs::write(&probe, source).is_err() {
+        return false;
+    }
+
+    cc::Build::new()
+        .cpp(true)
+        .file(&probe)
+        .cargo_warnings(false)
+        .try_expand()
+        .is_ok_and(|expanded| {
+            String::from_utf8_lossy(&expanded)
+                .lines()
+                .any(|line| line.trim() == "apt_inst_is_separate")
+        })
+}
+
+/// Whether the installed headers have `pkgDepCache::PhasingApplies`.
+fn has_phasing() -> bool {
+    let probe = match env::var("OUT_DIR") {
+        Ok(out_dir) => PathBuf::from(out_dir).join("apt-phasing.cpp"),
+        Err(_) => return false,
+    };
+    let source = "#include <apt-pkg/depcache.h>\n\
+                  bool probe(pkgDepCache &cache, pkgCache::PkgIterator pkg) {\n\
+                  \x20   return cache.PhasingApplies(pkg);\n\
+                  }\n";
+    if fs::write(&probe, source).is_err() {
+        return false;
+    }
+
+    cc::Build::new()
+        .cpp(true)
+        .flag("-std=gnu++17")
+        .file(&probe)
+        .cargo_metadata(false)
+        .cargo_warnings(false)
+        .try_compile("apt-phasing-probe")
+        .is_ok()
 }
@@ -0,0 +1,43 @@
+//! Compare two dpkg status files, e.g. from yesterday's image and today's.
+//!
+//! `cargo run --features serde --example diff -- [--json] old/status new/status`
+
+use std::env;
+use std::process;
+
+use apt_pkg_native::Cache;
+use apt_pkg_native::diff::PackageState;
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let json = args.first().is_some_and(|arg| arg == "--json");
+    if json {
+        args.remove(0);
+    }
+
+    if args.len() != 2 {
+        eprintln!("usage: diff [--json] OLD-STATUS NEW-STATUS");
+        process::exit(2);
+    }
+
+    let load = |path: &str| {
+        PackageState::from_status_file(path).unwrap_or_else(|err| {
+            eprintln!("couldn't read {path}: {err}");
+            process::exit(1);
+        })
+    };
+    let old = load(&args[0]);
+    let new = load(&args[1]);
+
+    let cache = Cache::get_singleton();
+    let diff = old.diff(&new, &cache);
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&diff).expect("serialising")
+        );
+    } else {
+        print!("{diff}");
+    }
+}
@@ -0,0 +1,17 @@
+//! Dump everything in the cache as JSON, e.g. to load into an asset database.
+//!
+//! `cargo run --features serde --example dump > inventory.json`
+
+use std::io;
+
+use apt_pkg_native::Cache;
+use apt_pkg_native::simple;
+
+fn main() {
+    let mut cache = Cache::get_singleton();
+    let inventory = simple::snapshot(&mut cache);
+
+    let stdout = io::stdout();
+    serde_json::to_writer_pretty(stdout.lock(), &inventory).expect("writing to stdout");
+    println!();
+}
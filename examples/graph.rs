@@ -0,0 +1,48 @@
+//! Draw the dependencies of some packages, or of everything installed.
+//!
+//! `cargo run --example graph -- [--graphml] [--depth N] [PACKAGE...] | dot -Tsvg > deps.svg`
+
+use std::env;
+use std::io;
+use std::process;
+
+use apt_pkg_native::Cache;
+use apt_pkg_native::graph::Graph;
+use apt_pkg_native::graph::GraphOpts;
+
+fn main() {
+    let mut opts = GraphOpts::default();
+    let mut graphml = false;
+    let mut roots = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--graphml" => graphml = true,
+            "--depth" => match args.next().and_then(|depth| depth.parse().ok()) {
+                Some(depth) => opts.max_depth = Some(depth),
+                None => {
+                    eprintln!("usage: graph [--graphml] [--depth N] [PACKAGE...]");
+                    process::exit(2);
+                }
+            },
+            _ => roots.push(arg),
+        }
+    }
+
+    let mut cache = Cache::get_singleton();
+    let graph = if roots.is_empty() {
+        Graph::installed(&mut cache, &opts)
+    } else {
+        let roots: Vec<&str> = roots.iter().map(String::as_str).collect();
+        Graph::from_roots(&mut cache, &roots, &opts)
+    };
+
+    let stdout = io::stdout().lock();
+    let written = if graphml {
+        graph.write_graphml(stdout)
+    } else {
+        graph.write_dot(stdout)
+    };
+    written.expect("writing to stdout");
+}
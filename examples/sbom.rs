@@ -0,0 +1,27 @@
+//! Write a bill of materials for everything installed, as SPDX or CycloneDX JSON.
+//!
+//! `cargo run --features serde --example sbom -- [--cyclonedx] [NAME]`
+
+use std::env;
+
+use apt_pkg_native::Cache;
+use apt_pkg_native::sbom::Sbom;
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let cyclonedx = args.first().is_some_and(|arg| arg == "--cyclonedx");
+    if cyclonedx {
+        args.remove(0);
+    }
+    let name = args.first().map(String::as_str).unwrap_or("installed");
+
+    let mut cache = Cache::get_singleton();
+    let sbom = Sbom::installed(&mut cache);
+
+    let json = if cyclonedx {
+        serde_json::to_string_pretty(&sbom.cyclonedx())
+    } else {
+        serde_json::to_string_pretty(&sbom.spdx(name))
+    };
+    println!("{}", json.expect("serialising"));
+}
@@ -0,0 +1,53 @@
+//! Downloading changelogs (or anything else) with apt's acquire methods, so `http`,
+//! `https`, `file`, and apt's internal `copy` and `store` URIs all work, as in `apt changelog`.
+
+use std::ffi;
+use std::fs;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use crate::error::AptError;
+use crate::raw;
+
+/// Fetch a whole changelog, e.g. from `VerView::changelog_uri()`.
+///
+/// This uses the proxy and other `Acquire` settings from the apt configuration. The methods
+/// doing the work are found in `Dir::Bin::Methods`, which is under any `CacheBuilder::with_root`,
+/// so won't normally be there while looking at another system.
+///
+/// The cache is locked while fetching, as apt's configuration is shared with it, so this
+/// will wait for any outstanding iterators to be dropped.
+pub fn fetch(uri: &str) -> Result<String, AptError> {
+    static FETCHES: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = std::env::temp_dir().join(format!(
+        "apt-pkg-native-fetch-{}-{}",
+        std::process::id(),
+        FETCHES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&dir).map_err(|err| AptError {
+        messages: vec![format!("couldn't create {}: {err}", dir.display())],
+    })?;
+
+    let c_uri = ffi::CString::new(uri).unwrap();
+    let c_dir = raw::path_to_cstring(&dir);
+    let name = ffi::CString::new("changelog").unwrap();
+
+    // nobody can rebuild, and so change the configuration, until the errors have been taken
+    let lock = raw::pkg_cache_get_singleton()
+        .lock()
+        .expect("poisoned mutex");
+    let result = if unsafe { raw::fetch_file(c_uri.as_ptr(), c_dir.as_ptr(), name.as_ptr()) } {
+        fs::read(dir.join("changelog"))
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .map_err(|err| AptError {
+                messages: vec![format!("fetched {uri}, but couldn't read it: {err}")],
+            })
+    } else {
+        Err(AptError::take("couldn't fetch changelog"))
+    };
+    drop(lock);
+
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
@@ -0,0 +1,258 @@
+//! Everything which has to be present for some packages to be installed, e.g. to work out
+//! what can be left out of an image.
+//!
+//! This isn't a resolver: one version of each package is picked up front, as set by
+//! `VersionChoice`, and conflicts are ignored, so the closure may not be installable.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::Cache;
+use crate::sane::DepView;
+use crate::sane::DependencyId;
+use crate::sane::VerView;
+use crate::sane::VersionId;
+
+/// Which version of each package to use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VersionChoice {
+    /// The version `apt install` would pick.
+    #[default]
+    Candidate,
+    /// The installed version, or the candidate, for packages which aren't installed.
+    Installed,
+}
+
+/// Which dependencies are followed. `Depends` and `Pre-Depends` always are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClosureOpts {
+    pub recommends: bool,
+    pub suggests: bool,
+    /// Whether a dependency can be satisfied by something which `Provides` it,
+    /// which is the only way to satisfy a dependency on a virtual package.
+    pub providers: bool,
+    pub versions: VersionChoice,
+}
+
+impl Default for ClosureOpts {
+    fn default() -> ClosureOpts {
+        ClosureOpts {
+            recommends: false,
+            suggests: false,
+            providers: true,
+            versions: VersionChoice::default(),
+        }
+    }
+}
+
+/// A version in the closure.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub id: VersionId,
+    pub package: String,
+    pub arch: String,
+    pub version: String,
+    /// The dependency which first needed this version, or `None` for the roots.
+    pub pulled_in_by: Option<Edge>,
+}
+
+/// A dependency of a version in the closure.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub from: VersionId,
+    pub dependency: DependencyId,
+    pub dep_type: String,
+    /// The package named by the dependency, which may be virtual.
+    pub target: String,
+    pub comp_type: Option<String>,
+    pub target_ver: Option<String>,
+}
+
+/// The result of `Cache::dependency_closure()`.
+#[derive(Clone, Debug, Default)]
+pub struct Closure {
+    members: Vec<Member>,
+    by_id: HashMap<VersionId, usize>,
+    unsatisfied: Vec<Edge>,
+}
+
+impl Closure {
+    pub(crate) fn new(cache: &mut Cache, roots: &[VersionId], opts: ClosureOpts) -> Closure {
+        let mut closure = Closure::default();
+        let mut queue = VecDeque::new();
+
+        for &root in roots {
+            let mut found = cache.version_by_id(root);
+            if let Some(ver) = found.next()
+                && closure.insert(member(&ver, None))
+            {
+                queue.push_back(root);
+            }
+        }
+
+        while let Some(id) = queue.pop_front() {
+            let mut found = cache.version_by_id(id);
+            let ver = found.next().expect("members are in the cache");
+
+            // the alternatives of an or-group, each with the versions which would do
+            let mut group = Vec::new();
+            let mut deps = ver.dep_iter();
+            while let Some(dep) = deps.next() {
+                if opts.follows(&dep.dep_type()) {
+                    group.push((Edge::new(id, &dep), choices(&dep, &opts)));
+                }
+
+                if !dep.is_or()
+                    && !group.is_empty()
+                    && let Some(added) = closure.satisfy(group.drain(..))
+                {
+                    queue.push_back(added);
+                }
+            }
+        }
+
+        closure
+    }
+
+    fn insert(&mut self, member: Member) -> bool {
+        if self.by_id.contains_key(&member.id) {
+            return false;
+        }
+        self.by_id.insert(member.id, self.members.len());
+        self.members.push(member);
+        true
+    }
+
+    /// Use the first alternative which can be satisfied, preferring the named package over
+    /// its providers, unless a later one already is. Returns what had to be added, if anything.
+    fn satisfy(&mut self, group: impl Iterator<Item = (Edge, Vec<Member>)>) -> Option<VersionId> {
+        let group: Vec<_> = group.collect();
+        if group
+            .iter()
+            .flat_map(|(_, choices)| choices)
+            .any(|choice| self.contains(choice.id))
+        {
+            return None;
+        }
+
+        let mut first = None;
+        for (edge, choices) in group {
+            if let Some(mut choice) = choices.into_iter().next() {
+                let id = choice.id;
+                choice.pulled_in_by = Some(edge);
+                self.insert(choice);
+                return Some(id);
+            }
+
+            first.get_or_insert(edge);
+        }
+
+        self.unsatisfied.extend(first);
+        None
+    }
+
+    /// The roots, then everything they pulled in, breadth first.
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    pub fn get(&self, id: VersionId) -> Option<&Member> {
+        self.by_id.get(&id).map(|&idx| &self.members[idx])
+    }
+
+    pub fn contains(&self, id: VersionId) -> bool {
+        self.by_id.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Dependencies which nothing could be found for, with the version chosen. For an
+    /// or-group, this is the first alternative.
+    pub fn unsatisfied(&self) -> &[Edge] {
+        &self.unsatisfied
+    }
+}
+
+impl Edge {
+    pub(crate) fn new(from: VersionId, dep: &DepView) -> Edge {
+        let comp_type = dep.comp_type();
+        let (comp_type, target_ver) = if comp_type.is_empty() {
+            (None, None)
+        } else {
+            (Some(comp_type), Some(dep.target_ver()))
+        };
+
+        Edge {
+            from,
+            dependency: dep.id(),
+            dep_type: dep.dep_type(),
+            target: dep.target_pkg().name(),
+            comp_type,
+            target_ver,
+        }
+    }
+}
+
+impl ClosureOpts {
+    fn follows(&self, dep_type: &str) -> bool {
+        match dep_type {
+            "Depends" | "PreDepends" => true,
+            "Recommends" => self.recommends,
+            "Suggests" => self.suggests,
+            _ => false,
+        }
+    }
+}
+
+fn member(ver: &VerView, pulled_in_by: Option<Edge>) -> Member {
+    Member {
+        id: ver.id(),
+        package: ver.package().name(),
+        arch: ver.arch(),
+        version: ver.version(),
+        pulled_in_by,
+    }
+}
+
+/// The versions which satisfy the dependency, and are the version chosen for their package.
+fn choices(dep: &DepView, opts: &ClosureOpts) -> Vec<Member> {
+    let target = dep.target_pkg().id();
+    let mut choices = Vec::new();
+
+    let mut vers = dep.all_targets();
+    while let Some(ver) = vers.next() {
+        let pkg = ver.package();
+        if !opts.providers && pkg.id() != target {
+            continue;
+        }
+
+        let wanted = match opts.versions {
+            VersionChoice::Candidate => pkg.candidate_version(),
+            VersionChoice::Installed => pkg.current_version().or_else(|| pkg.candidate_version()),
+        };
+
+        if wanted == Some(ver.version()) {
+            choices.push(member(&ver, None));
+        }
+    }
+
+    choices
+}
+
+/// As in a control file, e.g. `Depends: libc6 (>= 2.36)`.
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.dep_type, self.target)?;
+        if let (Some(comp_type), Some(target_ver)) = (&self.comp_type, &self.target_ver) {
+            write!(f, " ({comp_type} {target_ver})")?;
+        }
+        Ok(())
+    }
+}
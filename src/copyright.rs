@@ -0,0 +1,381 @@
+//! Reading machine-readable `debian/copyright` files (DEP-5), as installed in
+//! `/usr/share/doc/<package>/copyright`.
+//!
+//! See <https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/>.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::deb822;
+use crate::dpkg;
+use crate::error::Deb822Error;
+
+/// A whole copyright file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Copyright {
+    /// The URI of the format, which is how machine-readable files are recognised.
+    pub format: String,
+    pub upstream_name: Option<String>,
+    pub source: Option<String>,
+    /// The header's `Copyright` and `License`, which cover the package as a whole, if given.
+    pub copyright: Option<String>,
+    pub license: Option<License>,
+    /// In file order; for any file, the last stanza which matches it applies.
+    pub files: Vec<Files>,
+    /// Stand-alone `License` stanzas, giving the text for licenses named elsewhere.
+    pub licenses: Vec<License>,
+}
+
+/// A `Files` stanza.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Files {
+    /// The line (counting from one) the stanza started on.
+    pub line: usize,
+    /// Shell-style patterns, like `*` or `debian/*`.
+    pub patterns: Vec<String>,
+    pub copyright: Option<String>,
+    pub license: License,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct License {
+    /// The first line: a short name, or an expression like `GPL-2+ or Artistic`.
+    pub name: String,
+    /// The rest, if the license is spelled out here, with `.` lines turned back into blank lines.
+    pub text: Option<String>,
+}
+
+/// Parse a copyright file. Files which aren't machine-readable, i.e. have no `Format`
+/// in their first stanza, are an error, as are `Files` stanzas without a `License`.
+pub fn parse(text: &str) -> Result<Copyright, Deb822Error> {
+    let mut paragraphs = deb822::parse(text)?.into_iter();
+    let header = paragraphs.next().ok_or_else(|| Deb822Error {
+        line: 1,
+        message: "empty copyright file".to_string(),
+    })?;
+
+    let mut copyright = Copyright {
+        format: header
+            .get("Format")
+            .ok_or_else(|| Deb822Error {
+                line: header.line,
+                message: "not a machine-readable copyright file: no Format".to_string(),
+            })?
+            .to_string(),
+        upstream_name: header.get("Upstream-Name").map(str::to_string),
+        source: header.get("Source").map(str::to_string),
+        copyright: header.get("Copyright").map(trim_lines),
+        license: header.get("License").map(license),
+        ..Copyright::default()
+    };
+
+    for paragraph in paragraphs {
+        let name = match paragraph.get("License") {
+            Some(name) => license(name),
+            None if paragraph.get("Files").is_some() => {
+                return Err(Deb822Error {
+                    line: paragraph.line,
+                    message: "Files stanza without a License".to_string(),
+                });
+            }
+            None => continue,
+        };
+
+        match paragraph.get("Files") {
+            Some(patterns) => copyright.files.push(Files {
+                line: paragraph.line,
+                patterns: patterns.split_whitespace().map(str::to_string).collect(),
+                copyright: paragraph.get("Copyright").map(trim_lines),
+                license: name,
+            }),
+            None => copyright.licenses.push(name),
+        }
+    }
+
+    Ok(copyright)
+}
+
+impl Copyright {
+    /// Every license named in the file, as one SPDX expression, e.g.
+    /// `GPL-2.0-or-later AND (MIT OR Apache-2.0)`. `None` if there are no licenses at all.
+    ///
+    /// Debian's short names are mapped to SPDX identifiers where there's an obvious match,
+    /// and to `LicenseRef-<name>` otherwise.
+    pub fn spdx_expression(&self) -> Option<String> {
+        let mut terms: Vec<String> = Vec::new();
+        let names = self
+            .files
+            .iter()
+            .map(|files| &files.license)
+            .chain(self.license.iter());
+
+        for license in names {
+            let term = spdx_expression(&license.name);
+            if !term.is_empty() && !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+
+        match terms.len() {
+            0 => None,
+            1 => terms.pop(),
+            _ => Some(
+                terms
+                    .iter()
+                    .map(|term| parenthesise(term))
+                    .collect::<Vec<_>>()
+                    .join(" AND "),
+            ),
+        }
+    }
+}
+
+/// `PkgView::copyright`; the caller must hold the cache lock.
+pub(crate) fn read(name: &str) -> io::Result<Option<Copyright>> {
+    let root = dpkg::root_dir();
+    let doc = doc_dir(&root, name)?;
+    let text = match fs::read_to_string(doc.join("copyright")) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    // plenty of packages still have free-form copyright files
+    Ok(parse(&text).ok())
+}
+
+/// `/usr/share/doc/<name>` under the root. These are often symlinks to another package's
+/// directory; absolute links have to be kept inside the root.
+fn doc_dir(root: &Path, name: &str) -> io::Result<PathBuf> {
+    let doc = root.join("usr/share/doc").join(name);
+    match fs::read_link(&doc) {
+        Ok(target) if target.is_absolute() => Ok(root.join(
+            target
+                .strip_prefix("/")
+                .expect("absolute paths start with /"),
+        )),
+        Ok(_) => Ok(doc),
+        Err(err) if err.kind() == io::ErrorKind::InvalidInput => Ok(doc),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(doc),
+        Err(err) => Err(err),
+    }
+}
+
+/// `Copyright` is a list of lines, usually lined up after the field name.
+fn trim_lines(value: &str) -> String {
+    value.lines().map(str::trim).collect::<Vec<_>>().join("\n")
+}
+
+fn license(value: &str) -> License {
+    let (name, text) = match value.split_once('\n') {
+        Some((name, text)) => (name, Some(text)),
+        None => (value, None),
+    };
+
+    License {
+        name: name.trim().to_string(),
+        text: text.map(|text| {
+            text.lines()
+                .map(|line| if line.trim() == "." { "" } else { line })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }),
+    }
+}
+
+/// A DEP-5 license expression, like `GPL-2+ or Artistic, and BSD-3-clause`, in SPDX terms.
+/// As in SPDX, `and` binds tighter than `or`, but DEP-5's commas group everything before
+/// them, so that's `(GPL-2.0-or-later OR Artistic-1.0) AND BSD-3-Clause`.
+fn spdx_expression(name: &str) -> String {
+    let mut expression = String::new();
+    for group in name.split(',') {
+        let mut tokens = group.split_whitespace().peekable();
+        if expression.is_empty() {
+            expression = spdx_terms(tokens);
+            continue;
+        }
+
+        let op = match tokens.peek().map(|token| token.to_ascii_lowercase()) {
+            Some(op) if op == "or" || op == "and" => {
+                tokens.next();
+                op.to_ascii_uppercase()
+            }
+            _ => "AND".to_string(),
+        };
+        let terms = spdx_terms(tokens);
+        if !terms.is_empty() {
+            expression = format!(
+                "{} {op} {}",
+                parenthesise(&expression),
+                parenthesise(&terms)
+            );
+        }
+    }
+
+    expression
+}
+
+/// Licenses, `or`, `and` and `with`, without any commas.
+fn spdx_terms<'a>(mut tokens: impl Iterator<Item = &'a str> + Clone) -> String {
+    let mut out: Vec<String> = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token.to_ascii_lowercase().as_str() {
+            "or" => out.push("OR".to_string()),
+            "and" => out.push("AND".to_string()),
+            "with" => {
+                // `with Foo exception`
+                let exception = tokens.next().unwrap_or("unknown");
+                if tokens.clone().next() == Some("exception") {
+                    tokens.next();
+                }
+                match exception_id(exception) {
+                    Some(id) => {
+                        out.push("WITH".to_string());
+                        out.push(id.to_string());
+                    }
+                    // SPDX only allows its own exceptions after WITH, so it's all one license
+                    None => {
+                        let license = out.pop().unwrap_or_default();
+                        let license = license.strip_prefix("LicenseRef-").unwrap_or(&license);
+                        out.push(license_ref(&format!(
+                            "{license}-with-{exception}-exception"
+                        )));
+                    }
+                }
+            }
+            _ => out.push(license_id(token)),
+        }
+    }
+
+    out.join(" ")
+}
+
+/// Brackets around an expression of more than one license, if it hasn't got them already.
+fn parenthesise(expression: &str) -> String {
+    let mut depth = 0;
+    let mut wrapped = expression.starts_with('(');
+    for (i, c) in expression.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 && i + 1 < expression.len() {
+            wrapped = false;
+        }
+    }
+
+    if wrapped || !expression.contains(' ') {
+        expression.to_string()
+    } else {
+        format!("({expression})")
+    }
+}
+
+/// SPDX identifiers which Debian mostly uses as-is, give or take case.
+const SPDX_IDS: &[&str] = &[
+    "0BSD",
+    "Apache-1.0",
+    "Apache-1.1",
+    "Apache-2.0",
+    "Artistic-1.0",
+    "Artistic-2.0",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "BSD-4-Clause",
+    "BSL-1.0",
+    "CC-BY-3.0",
+    "CC-BY-4.0",
+    "CC-BY-SA-3.0",
+    "CC-BY-SA-4.0",
+    "CC0-1.0",
+    "curl",
+    "EPL-1.0",
+    "EPL-2.0",
+    "FTL",
+    "ISC",
+    "LPPL-1.3c",
+    "MIT",
+    "MPL-1.1",
+    "MPL-2.0",
+    "OFL-1.1",
+    "OpenSSL",
+    "Unlicense",
+    "WTFPL",
+    "X11",
+    "Zlib",
+    "ZPL-2.1",
+];
+
+fn license_id(short: &str) -> String {
+    let (base, plus) = match short.strip_suffix('+') {
+        Some(base) => (base, true),
+        None => (short, false),
+    };
+
+    // the GNU licenses have their "or later" in the identifier
+    for family in ["GPL", "LGPL", "AGPL", "GFDL"] {
+        if let Some(version) = base
+            .strip_prefix(family)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .filter(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+            .filter(|version| !version.is_empty())
+        {
+            let version = if version.contains('.') {
+                version.to_string()
+            } else {
+                format!("{version}.0")
+            };
+            let suffix = if plus { "or-later" } else { "only" };
+            return format!("{family}-{version}-{suffix}");
+        }
+    }
+
+    let id = match base.to_ascii_lowercase().as_str() {
+        "expat" => "MIT".to_string(),
+        "artistic" => "Artistic-1.0".to_string(),
+        "apache-2" => "Apache-2.0".to_string(),
+        "psf-2" => "PSF-2.0".to_string(),
+        "python" => "Python-2.0".to_string(),
+        "perl" => return "(Artistic-1.0-Perl OR GPL-1.0-or-later)".to_string(),
+        "public-domain" => "LicenseRef-public-domain".to_string(),
+        lower => match SPDX_IDS.iter().find(|id| id.to_ascii_lowercase() == lower) {
+            Some(id) => id.to_string(),
+            None => return license_ref(short),
+        },
+    };
+
+    if plus { format!("{id}+") } else { id }
+}
+
+/// The exceptions SPDX has an identifier for; others become part of a `LicenseRef`.
+fn exception_id(short: &str) -> Option<&'static str> {
+    match short.to_ascii_lowercase().as_str() {
+        "autoconf" => Some("Autoconf-exception-3.0"),
+        "bison" => Some("Bison-exception-2.2"),
+        "classpath" => Some("Classpath-exception-2.0"),
+        "font" => Some("Font-exception-2.0"),
+        "gcc" => Some("GCC-exception-3.1"),
+        _ => None,
+    }
+}
+
+/// SPDX's escape hatch: letters, digits, `.` and `-` only.
+fn license_ref(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("LicenseRef-{name}")
+}
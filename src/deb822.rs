@@ -0,0 +1,84 @@
+//! Reading the RFC822-ish "control file" format used all over Debian: `debian/control`,
+//! the dpkg status file, `Packages` lists, `debian/copyright`, ...
+//!
+//! This is a plain parser, without any of apt's knowledge about which fields mean what.
+
+use crate::error::Deb822Error;
+
+/// A block of fields, separated from the next by a blank line.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Paragraph {
+    /// The line (counting from one) the paragraph started on.
+    pub line: usize,
+    fields: Vec<(String, String)>,
+}
+
+impl Paragraph {
+    /// A field's value. Names are case-insensitive, as in dpkg.
+    ///
+    /// The first line is trimmed; continuation lines follow it, after a `\n`, with their
+    /// leading space removed. `.` lines, representing blank lines, are left alone.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// All the fields, in file order.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+/// Split a whole file into paragraphs. Comment lines, starting with `#`, are skipped.
+pub fn parse(text: &str) -> Result<Vec<Paragraph>, Deb822Error> {
+    let mut paragraphs = Vec::new();
+    let mut current = Paragraph::default();
+
+    for (idx, line) in text.lines().enumerate() {
+        let line_number = idx + 1;
+
+        if line.trim().is_empty() {
+            if !current.fields.is_empty() {
+                paragraphs.push(current);
+            }
+            current = Paragraph::default();
+            continue;
+        }
+
+        if line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with(' ') || line.starts_with('\t') {
+            let (_, value) = current.fields.last_mut().ok_or_else(|| Deb822Error {
+                line: line_number,
+                message: "continuation line outside a field".to_string(),
+            })?;
+            value.push('\n');
+            value.push_str(&line[1..]);
+            continue;
+        }
+
+        let (name, value) = line.split_once(':').ok_or_else(|| Deb822Error {
+            line: line_number,
+            message: format!("expected a field, like 'Name: value', not {line:?}"),
+        })?;
+
+        if current.fields.is_empty() {
+            current.line = line_number;
+        }
+        current
+            .fields
+            .push((name.trim_end().to_string(), value.trim().to_string()));
+    }
+
+    if !current.fields.is_empty() {
+        paragraphs.push(current);
+    }
+
+    Ok(paragraphs)
+}
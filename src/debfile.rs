@@ -0,0 +1,156 @@
+//! Reading `.deb` files directly, with `apt-inst`'s `debDebFile`, without `dpkg` or a cache.
+
+use std::ffi;
+use std::path::Path;
+
+use crate::error::AptError;
+use crate::raw;
+
+/// An open `.deb` archive. The control file is read and checked when it is opened.
+#[derive(Debug)]
+pub struct DebFile {
+    ptr: raw::PDebFile,
+}
+
+/// What sort of thing a member of the data tarball is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemberKind {
+    File,
+    HardLink,
+    SymbolicLink,
+    CharDevice,
+    BlockDevice,
+    Directory,
+    Fifo,
+    /// A type apt doesn't have a name for, with its code, from a malformed or unusual `.deb`.
+    Other(u8),
+}
+
+/// An entry in the data tarball, roughly what `dpkg --contents` shows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    /// The path as stored in the tarball, normally starting `./`.
+    pub path: String,
+    pub kind: MemberKind,
+    /// Permission bits, without the file type.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub mtime: u64,
+    pub link_target: Option<String>,
+}
+
+impl DebFile {
+    /// Open a `.deb` and read its control file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<DebFile, AptError> {
+        raw::init_config_system_once();
+
+        let path = raw::path_to_cstring(path.as_ref());
+        let ptr = unsafe { raw::deb_file_open(path.as_ptr()) };
+        if ptr.is_null() {
+            return Err(AptError::take("couldn't open deb file"));
+        }
+
+        Ok(DebFile { ptr })
+    }
+
+    /// The whole of the `control` file.
+    pub fn control(&self) -> String {
+        unsafe {
+            ffi::CStr::from_ptr(raw::deb_file_control(self.ptr))
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+
+    /// A single field from the `control` file, e.g. `Package` or `Depends`.
+    pub fn control_field(&self, name: &str) -> Option<String> {
+        let name = ffi::CString::new(name).unwrap();
+        unsafe { raw::make_owned_string_free(raw::deb_file_control_field(self.ptr, name.as_ptr())) }
+    }
+
+    /// Another member of the control tarball, like `postinst`, `conffiles` or `md5sums`.
+    ///
+    /// `Ok(None)` means the package doesn't ship one.
+    pub fn control_member(&self, name: &str) -> Result<Option<Vec<u8>>, AptError> {
+        let name = ffi::CString::new(name).unwrap();
+        self.extract(|ptr, len| unsafe { raw::deb_file_control_member(ptr, name.as_ptr(), len) })
+    }
+
+    /// Everything in the data tarball, in archive order.
+    pub fn data_members(&self) -> Result<Vec<Member>, AptError> {
+        unsafe {
+            if !raw::deb_file_list_data(self.ptr) {
+                return Err(AptError::take("couldn't read data tarball"));
+            }
+
+            Ok((0..raw::deb_file_data_count(self.ptr))
+                .map(|idx| Member {
+                    path: ffi::CStr::from_ptr(raw::deb_file_data_name(self.ptr, idx))
+                        .to_string_lossy()
+                        .into_owned(),
+                    kind: member_kind(raw::deb_file_data_type(self.ptr, idx)),
+                    mode: raw::deb_file_data_mode(self.ptr, idx) & 0o7777,
+                    uid: raw::deb_file_data_uid(self.ptr, idx),
+                    gid: raw::deb_file_data_gid(self.ptr, idx),
+                    size: raw::deb_file_data_size(self.ptr, idx),
+                    mtime: raw::deb_file_data_mtime(self.ptr, idx),
+                    link_target: {
+                        let target = raw::deb_file_data_link_target(self.ptr, idx);
+                        if target.is_null() {
+                            None
+                        } else {
+                            Some(ffi::CStr::from_ptr(target).to_string_lossy().into_owned())
+                        }
+                    },
+                })
+                .collect())
+        }
+    }
+
+    /// The contents of a single file in the data tarball. `./usr/bin/foo`,
+    /// `usr/bin/foo` and `/usr/bin/foo` all refer to the same member.
+    ///
+    /// `Ok(None)` means there's no such member.
+    pub fn data_member(&self, path: &str) -> Result<Option<Vec<u8>>, AptError> {
+        let path = ffi::CString::new(path).unwrap();
+        self.extract(|ptr, len| unsafe { raw::deb_file_data_member(ptr, path.as_ptr(), len) })
+    }
+
+    fn extract<F>(&self, f: F) -> Result<Option<Vec<u8>>, AptError>
+    where
+        F: FnOnce(raw::PDebFile, *mut usize) -> *mut libc::c_char,
+    {
+        let mut len = 0;
+        let buf = f(self.ptr, &mut len);
+        if buf.is_null() {
+            if unsafe { raw::apt_error_pending() } {
+                return Err(AptError::take("couldn't read deb file"));
+            }
+            return Ok(None);
+        }
+
+        Ok(unsafe { raw::make_owned_bytes_free(buf, len) })
+    }
+}
+
+impl Drop for DebFile {
+    fn drop(&mut self) {
+        unsafe { raw::deb_file_release(self.ptr) }
+    }
+}
+
+fn member_kind(raw: i32) -> MemberKind {
+    // pkgDirStream::Item::Type_t
+    match raw {
+        0 => MemberKind::File,
+        1 => MemberKind::HardLink,
+        2 => MemberKind::SymbolicLink,
+        3 => MemberKind::CharDevice,
+        4 => MemberKind::BlockDevice,
+        5 => MemberKind::Directory,
+        6 => MemberKind::Fifo,
+        other => MemberKind::Other(u8::try_from(other).unwrap_or(u8::MAX)),
+    }
+}
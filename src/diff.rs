@@ -0,0 +1,286 @@
+//! Comparing what's installed in two places, or at two points in time.
+//!
+//! Packages are identified by name and the architecture their version was built for,
+//! so an `Architecture: all` package is `foo:all`, as in the dpkg status file.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::Cache;
+use crate::deb822;
+use crate::simple::Origin;
+use crate::snapshot::Snapshot;
+
+/// An installed package, from one side of a diff.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Installed {
+    pub name: String,
+    pub arch: String,
+    pub version: String,
+    /// Where the installed version is available from, like `Debian/bookworm-security`,
+    /// if that's known.
+    pub origin: Option<String>,
+}
+
+/// A package installed on both sides of a diff, which has changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Changed {
+    pub name: String,
+    pub arch: String,
+    pub old_version: String,
+    pub new_version: String,
+    pub old_origin: Option<String>,
+    pub new_origin: Option<String>,
+}
+
+/// Everything installed at one point, e.g. on a machine, or in an image.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PackageState {
+    packages: BTreeMap<(String, String), Installed>,
+}
+
+/// What changed between two `PackageState`s. Everything is sorted by name, then architecture.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diff {
+    pub added: Vec<Installed>,
+    pub removed: Vec<Installed>,
+    pub upgraded: Vec<Changed>,
+    pub downgraded: Vec<Changed>,
+    /// Packages which are now installed from somewhere else. Packages appear here
+    /// even if they're also up or downgraded, but only if both origins are known.
+    pub origin_changed: Vec<Changed>,
+}
+
+impl PackageState {
+    /// What's installed according to the cache.
+    pub fn from_cache(cache: &mut Cache) -> PackageState {
+        let mut state = PackageState::default();
+        let mut pkgs = cache.iter();
+        while let Some(pkg) = pkgs.next() {
+            let current = match pkg.current_version() {
+                Some(current) => current,
+                None => continue,
+            };
+
+            let mut vers = pkg.versions();
+            while let Some(ver) = vers.next() {
+                if ver.version() != current {
+                    continue;
+                }
+
+                let origin = ver
+                    .origin_iter()
+                    .filter_map(|ver_file| Origin::from_ver_file(ver_file))
+                    .find_map(|origin| describe(&origin));
+
+                state.insert(Installed {
+                    name: pkg.name(),
+                    arch: ver.arch(),
+                    version: current,
+                    origin,
+                });
+                break;
+            }
+        }
+
+        state
+    }
+
+    /// What was installed when the snapshot was taken.
+    pub fn from_snapshot(snap: &Snapshot) -> PackageState {
+        let mut state = PackageState::default();
+        for (_, pkg) in snap.packages() {
+            let current = match pkg.current {
+                Some(current) => current,
+                None => continue,
+            };
+
+            let ver = snap.version(current);
+            state.insert(Installed {
+                name: pkg.name.clone(),
+                arch: ver.arch.clone(),
+                version: ver.version.clone(),
+                origin: snap.files(current).find_map(|(_, origin)| describe(origin)),
+            });
+        }
+
+        state
+    }
+
+    /// What's installed according to a dpkg status file, like `/var/lib/dpkg/status`.
+    /// Packages which have been removed, but still have their config files, aren't included.
+    ///
+    /// Status files don't say where anything came from, so origins are never known.
+    pub fn from_status_file<P: AsRef<Path>>(path: P) -> io::Result<PackageState> {
+        let text = fs::read_to_string(path)?;
+        let paragraphs =
+            deb822::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        let mut state = PackageState::default();
+        for paragraph in paragraphs {
+            let installed = paragraph
+                .get("Status")
+                .and_then(|status| status.split_whitespace().nth(2))
+                .is_some_and(|status| status != "not-installed" && status != "config-files");
+            if !installed {
+                continue;
+            }
+
+            let field = |name| {
+                paragraph.get(name).map(str::to_string).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: installed package has no {name}", paragraph.line),
+                    )
+                })
+            };
+
+            state.insert(Installed {
+                name: field("Package")?,
+                arch: field("Architecture")?,
+                version: field("Version")?,
+                origin: None,
+            });
+        }
+
+        Ok(state)
+    }
+
+    fn insert(&mut self, installed: Installed) {
+        let key = (installed.name.clone(), installed.arch.clone());
+        self.packages.insert(key, installed);
+    }
+
+    /// Everything installed, sorted by name, then architecture.
+    pub fn packages(&self) -> impl Iterator<Item = &Installed> {
+        self.packages.values()
+    }
+
+    pub fn get(&self, name: &str, arch: &str) -> Option<&Installed> {
+        self.packages.get(&(name.to_string(), arch.to_string()))
+    }
+
+    /// What has to happen to get from this state to `newer`. The cache is only used to
+    /// compare versions.
+    pub fn diff(&self, newer: &PackageState, cache: &Cache) -> Diff {
+        let mut diff = Diff::default();
+
+        for (key, old) in &self.packages {
+            let new = match newer.packages.get(key) {
+                Some(new) => new,
+                None => {
+                    diff.removed.push(old.clone());
+                    continue;
+                }
+            };
+
+            let changed = Changed {
+                name: old.name.clone(),
+                arch: old.arch.clone(),
+                old_version: old.version.clone(),
+                new_version: new.version.clone(),
+                old_origin: old.origin.clone(),
+                new_origin: new.origin.clone(),
+            };
+
+            if old.origin.is_some() && new.origin.is_some() && old.origin != new.origin {
+                diff.origin_changed.push(changed.clone());
+            }
+
+            match cache.compare_versions(&old.version, &new.version) {
+                Ordering::Less => diff.upgraded.push(changed),
+                Ordering::Greater => diff.downgraded.push(changed),
+                Ordering::Equal => (),
+            }
+        }
+
+        for (key, new) in &newer.packages {
+            if !self.packages.contains_key(key) {
+                diff.added.push(new.clone());
+            }
+        }
+
+        diff
+    }
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.upgraded.is_empty()
+            && self.downgraded.is_empty()
+            && self.origin_changed.is_empty()
+    }
+}
+
+/// The archive this came from, unless it's just the dpkg status file.
+fn describe(origin: &Origin) -> Option<String> {
+    if origin.index_type == "Debian dpkg status file" {
+        return None;
+    }
+
+    Some(match origin.origin {
+        Some(ref name) => format!("{}/{}", name, origin.archive),
+        None => origin.archive.clone(),
+    })
+}
+
+impl fmt::Display for Installed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{} {}", self.name, self.arch, self.version)?;
+        if let Some(ref origin) = self.origin {
+            write!(f, " from {origin}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Changed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{} {} -> {}",
+            self.name, self.arch, self.old_version, self.new_version
+        )?;
+        if self.old_origin != self.new_origin {
+            let unknown = || "?".to_string();
+            write!(
+                f,
+                " (from {} -> {})",
+                self.old_origin.clone().unwrap_or_else(unknown),
+                self.new_origin.clone().unwrap_or_else(unknown),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Like a patch: `+` for added, `-` for removed, `^`/`v` for up/downgrades, `~` for moves.
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for installed in &self.added {
+            writeln!(f, "+ {installed}")?;
+        }
+        for installed in &self.removed {
+            writeln!(f, "- {installed}")?;
+        }
+        for changed in &self.upgraded {
+            writeln!(f, "^ {changed}")?;
+        }
+        for changed in &self.downgraded {
+            writeln!(f, "v {changed}")?;
+        }
+        for changed in &self.origin_changed {
+            writeln!(f, "~ {changed}")?;
+        }
+        Ok(())
+    }
+}
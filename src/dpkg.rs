@@ -0,0 +1,454 @@
+//! What dpkg knows about installed packages: their entries in the status file,
+//! and the rest of its database, under `/var/lib/dpkg`.
+
+use std::ffi;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::deb822;
+use crate::error::AptError;
+use crate::error::Deb822Error;
+use crate::raw;
+
+/// A package's entry in `/var/lib/dpkg/status`, as far as installation goes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstalledRecord {
+    pub package: String,
+    pub version: String,
+    pub arch: String,
+    pub status: Status,
+    pub conffiles: Vec<Conffile>,
+    /// The version which was last successfully configured, if any.
+    pub config_version: Option<String>,
+    /// In KiB, as estimated by the package.
+    pub installed_size: Option<u64>,
+}
+
+/// The `Status:` field: `want flag state`, e.g. `install ok installed`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Status {
+    pub want: Want,
+    pub flag: Flag,
+    pub state: State,
+}
+
+/// What the user asked for, i.e. the selection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Want {
+    Unknown,
+    Install,
+    Hold,
+    Deinstall,
+    Purge,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Flag {
+    Ok,
+    /// The package is broken, and must be reinstalled before it can be removed.
+    ReinstReq,
+}
+
+/// How far through (un)installation the package is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum State {
+    NotInstalled,
+    ConfigFiles,
+    HalfInstalled,
+    Unpacked,
+    HalfConfigured,
+    TriggersAwaited,
+    TriggersPending,
+    Installed,
+}
+
+/// A file under `/etc` (normally) which dpkg won't overwrite if it's been changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conffile {
+    pub path: String,
+    /// The md5 of the file as the package shipped it, in hex, or `newconffile`
+    /// if the package hasn't been configured since it was added.
+    pub md5: String,
+    /// The package no longer ships this file.
+    pub obsolete: bool,
+    /// The package asked for this file to be removed on upgrade.
+    pub remove_on_upgrade: bool,
+}
+
+impl InstalledRecord {
+    /// Read a single status file stanza, like `VerFileView::record()` returns.
+    pub fn parse(record: &str) -> Result<InstalledRecord, Deb822Error> {
+        let paragraph = deb822::parse(record)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| invalid(1, "empty record".to_string()))?;
+        let line = paragraph.line;
+
+        let field = |name: &str| {
+            paragraph
+                .get(name)
+                .map(str::to_string)
+                .ok_or_else(|| invalid(line, format!("no {name} field")))
+        };
+
+        let conffiles = match paragraph.get("Conffiles") {
+            Some(conffiles) => conffiles
+                .lines()
+                .filter(|conffile| !conffile.trim().is_empty())
+                .map(|conffile| {
+                    Conffile::parse(conffile)
+                        .ok_or_else(|| invalid(line, format!("bad conffile: {conffile:?}")))
+                })
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+
+        let installed_size = match paragraph.get("Installed-Size") {
+            Some(size) => Some(
+                size.parse()
+                    .map_err(|_| invalid(line, format!("bad Installed-Size: {size:?}")))?,
+            ),
+            None => None,
+        };
+
+        let status = field("Status")?;
+        Ok(InstalledRecord {
+            package: field("Package")?,
+            version: field("Version")?,
+            arch: field("Architecture")?,
+            status: Status::parse(&status)
+                .ok_or_else(|| invalid(line, format!("bad Status: {status:?}")))?,
+            conffiles,
+            config_version: paragraph.get("Config-Version").map(str::to_string),
+            installed_size,
+        })
+    }
+}
+
+impl Status {
+    fn parse(status: &str) -> Option<Status> {
+        let mut words = status.split_whitespace();
+        let want = match words.next()? {
+            "unknown" => Want::Unknown,
+            "install" => Want::Install,
+            "hold" => Want::Hold,
+            "deinstall" => Want::Deinstall,
+            "purge" => Want::Purge,
+            _ => return None,
+        };
+        let flag = match words.next()? {
+            "ok" => Flag::Ok,
+            "reinstreq" => Flag::ReinstReq,
+            _ => return None,
+        };
+        let state = match words.next()? {
+            "not-installed" => State::NotInstalled,
+            "config-files" => State::ConfigFiles,
+            "half-installed" => State::HalfInstalled,
+            "unpacked" => State::Unpacked,
+            "half-configured" => State::HalfConfigured,
+            "triggers-awaited" => State::TriggersAwaited,
+            "triggers-pending" => State::TriggersPending,
+            "installed" => State::Installed,
+            _ => return None,
+        };
+
+        if words.next().is_some() {
+            return None;
+        }
+
+        Some(Status { want, flag, state })
+    }
+}
+
+impl Conffile {
+    /// ` /etc/foo.conf 0123...cdef [obsolete] [remove-on-upgrade]`
+    fn parse(line: &str) -> Option<Conffile> {
+        let mut words = line.split_whitespace();
+        let mut conffile = Conffile {
+            path: words.next()?.to_string(),
+            md5: words.next()?.to_string(),
+            obsolete: false,
+            remove_on_upgrade: false,
+        };
+
+        for flag in words {
+            match flag {
+                "obsolete" => conffile.obsolete = true,
+                "remove-on-upgrade" => conffile.remove_on_upgrade = true,
+                _ => return None,
+            }
+        }
+
+        Some(conffile)
+    }
+}
+
+fn invalid(line: usize, message: String) -> Deb822Error {
+    Deb822Error { line, message }
+}
+
+/// How an installed file compares to what the package shipped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileStatus {
+    Ok,
+    Modified,
+    Missing,
+    /// The file is there, but couldn't be read, e.g. due to permissions.
+    Unreadable(String),
+}
+
+/// One file checked by `PkgView::verify()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Verified {
+    /// Where the file is expected, after any diversions, relative to the system's root.
+    pub path: PathBuf,
+    /// Conffiles are checked against the status file, not `md5sums`, and are expected
+    /// to be changed by administrators.
+    pub conffile: bool,
+    pub status: FileStatus,
+}
+
+/// A file which has been moved out of the way, so a package can't overwrite it,
+/// as managed by `dpkg-divert`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diversion {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// The package whose version of the file is left at `from`, or `None` for
+    /// a local diversion, made by an administrator.
+    pub package: Option<String>,
+}
+
+impl Diversion {
+    /// Whether the diversion applies to `package`'s copy of the file.
+    fn applies_to(&self, package: &str) -> bool {
+        self.package.as_deref() != Some(package)
+    }
+}
+
+/// Where dpkg keeps its database, normally `/var/lib/dpkg`, under the configured root.
+///
+/// The caller must hold the cache lock, as this reads the configuration.
+pub(crate) fn admin_dir() -> PathBuf {
+    let name = ffi::CString::new("Dir::State::status").unwrap();
+    let status = unsafe { raw::make_owned_string_free(raw::config_find_file(name.as_ptr())) }
+        .expect("there's always a status file");
+    Path::new(&status)
+        .parent()
+        .expect("the status file is in a directory")
+        .to_path_buf()
+}
+
+/// Everything in the `diversions` file, which is sets of three lines: from, to, and package.
+pub fn read_diversions(admin_dir: &Path) -> io::Result<Vec<Diversion>> {
+    let bytes = match fs::read(admin_dir.join("diversions")) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let lines = lines(&bytes);
+    if !lines.len().is_multiple_of(3) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "diversions file is truncated",
+        ));
+    }
+
+    Ok(lines
+        .chunks(3)
+        .map(|diversion| {
+            let package = OsStr::from_bytes(diversion[2]).to_string_lossy();
+            Diversion {
+                from: path(diversion[0]),
+                to: path(diversion[1]),
+                package: if package == ":" {
+                    None
+                } else {
+                    Some(package.into_owned())
+                },
+            }
+        })
+        .collect())
+}
+
+/// `PkgView::installed_files`, from `info/<package>[:arch].list`.
+pub(crate) fn installed_files(name: &str, arch: &str) -> io::Result<Vec<PathBuf>> {
+    let admin_dir = admin_dir();
+    let bytes = read_info(&admin_dir, name, arch, "list")?.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("no file list for {name}"))
+    })?;
+
+    let diversions = read_diversions(&admin_dir)?;
+    Ok(lines(&bytes)
+        .into_iter()
+        .map(|line| divert(name, path(line), &diversions))
+        .collect())
+}
+
+/// `PkgView::verify`: the `md5sums` file, then the conffiles.
+pub(crate) fn verify(
+    name: &str,
+    arch: &str,
+    record: &InstalledRecord,
+) -> io::Result<Vec<Verified>> {
+    let admin_dir = admin_dir();
+    let root = root_dir();
+    let diversions = read_diversions(&admin_dir)?;
+    let conffiles: Vec<&Conffile> = record
+        .conffiles
+        .iter()
+        // there's nothing to compare against for these
+        .filter(|conffile| !conffile.obsolete && conffile.md5 != "newconffile")
+        .collect();
+
+    let mut checks = Vec::new();
+    let md5sums = read_info(&admin_dir, name, arch, "md5sums")?.unwrap_or_default();
+    for line in lines(&md5sums) {
+        // `<md5>  <path>`, where the path has no leading slash
+        let (md5, rest) = line.split_at(line.iter().position(|&b| b == b' ').unwrap_or(line.len()));
+        let listed = Path::new("/").join(path(rest.trim_ascii_start()));
+        if conffiles
+            .iter()
+            .any(|conffile| Path::new(&conffile.path) == listed)
+        {
+            continue;
+        }
+
+        let md5 = OsStr::from_bytes(md5).to_string_lossy();
+        checks.push(check(&root, divert(name, listed, &diversions), &md5, false));
+    }
+
+    for conffile in conffiles {
+        let listed = PathBuf::from(&conffile.path);
+        checks.push(check(
+            &root,
+            divert(name, listed, &diversions),
+            &conffile.md5,
+            true,
+        ));
+    }
+
+    Ok(checks)
+}
+
+fn check(root: &Path, path: PathBuf, expected: &str, conffile: bool) -> Verified {
+    let on_disk = root.join(path.strip_prefix("/").unwrap_or(&path));
+
+    let result = match fs::symlink_metadata(&on_disk) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => FileStatus::Missing,
+        Err(err) => FileStatus::Unreadable(err.to_string()),
+        Ok(_) => {
+            let c_path = raw::path_to_cstring(&on_disk);
+            match unsafe { raw::make_owned_string_free(raw::file_md5(c_path.as_ptr())) } {
+                Some(actual) if actual.eq_ignore_ascii_case(expected) => FileStatus::Ok,
+                Some(_) => FileStatus::Modified,
+                None => FileStatus::Unreadable(AptError::take("couldn't hash file").to_string()),
+            }
+        }
+    };
+
+    Verified {
+        path,
+        conffile,
+        status: result,
+    }
+}
+
+/// Where `name`'s copy of `listed` really is.
+fn divert(name: &str, listed: PathBuf, diversions: &[Diversion]) -> PathBuf {
+    diversions
+        .iter()
+        .find(|diversion| diversion.from == listed && diversion.applies_to(name))
+        .map_or(listed, |diversion| diversion.to.clone())
+}
+
+/// `info/<package>:<arch>.<ext>` or `info/<package>.<ext>`, if there is one.
+fn read_info(admin_dir: &Path, name: &str, arch: &str, ext: &str) -> io::Result<Option<Vec<u8>>> {
+    let info = admin_dir.join("info");
+
+    // only `Multi-Arch: same` packages have the architecture in the name
+    for file in [format!("{name}:{arch}.{ext}"), format!("{name}.{ext}")] {
+        match fs::read(info.join(file)) {
+            Ok(bytes) => return Ok(Some(bytes)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(None)
+}
+
+/// The system being looked at, as set by `CacheBuilder::with_root`.
+pub(crate) fn root_dir() -> PathBuf {
+    let name = ffi::CString::new("RootDir").unwrap();
+    let root =
+        unsafe { raw::make_owned_string_free(raw::config_find(name.as_ptr())) }.unwrap_or_default();
+    if root.is_empty() {
+        PathBuf::from("/")
+    } else {
+        PathBuf::from(root)
+    }
+}
+
+/// `Cache::package_owning`; the caller must hold the cache lock.
+pub(crate) fn package_owning(path: &Path) -> io::Result<Vec<String>> {
+    let admin_dir = admin_dir();
+    let diversions = read_diversions(&admin_dir)?;
+
+    // The lists have the names the packages asked for, so a diverted file is listed at `from`.
+    let diverted_here = diversions.iter().find(|diversion| diversion.to == path);
+    let diverted_away = diversions.iter().find(|diversion| diversion.from == path);
+    let listed = diverted_here.map_or(path, |diversion| diversion.from.as_path());
+
+    let mut owners = Vec::new();
+    for entry in fs::read_dir(admin_dir.join("info"))? {
+        let entry_path = entry?.path();
+        if entry_path.extension() != Some(OsStr::new("list")) {
+            continue;
+        }
+
+        let owner = match entry_path.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        let name = owner.split(':').next().unwrap_or(&owner);
+
+        let here = match (diverted_here, diverted_away) {
+            (Some(diversion), _) => diversion.applies_to(name),
+            (None, Some(diversion)) => !diversion.applies_to(name),
+            (None, None) => true,
+        };
+        if !here {
+            continue;
+        }
+
+        let bytes = fs::read(&entry_path)?;
+        if lines(&bytes).into_iter().any(|line| path_eq(line, listed)) {
+            owners.push(owner);
+        }
+    }
+
+    owners.sort();
+    Ok(owners)
+}
+
+fn lines(bytes: &[u8]) -> Vec<&[u8]> {
+    bytes
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+fn path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+fn path_eq(line: &[u8], path: &Path) -> bool {
+    line == path.as_os_str().as_bytes()
+}
@@ -0,0 +1,121 @@
+//! Errors reported by `libapt-pkg`, which keeps a (thread local) stack of messages
+//! instead of returning anything useful.
+
+use std::error;
+use std::fmt;
+
+use crate::raw;
+
+/// The messages `libapt-pkg` had queued up when an operation failed, oldest first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AptError {
+    pub messages: Vec<String>,
+}
+
+impl AptError {
+    /// Drain the apt error stack. If apt failed without saying why, `fallback` is used.
+    pub(crate) fn take(fallback: &str) -> AptError {
+        let mut messages = Vec::new();
+        while let Some(message) = unsafe { raw::make_owned_string_free(raw::apt_error_pop()) } {
+            messages.push(message);
+        }
+
+        if messages.is_empty() {
+            messages.push(fallback.to_string());
+        }
+
+        AptError { messages }
+    }
+}
+
+impl fmt::Display for AptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.messages.join("; "))
+    }
+}
+
+impl error::Error for AptError {}
+
+/// Why `Cache::select()` didn't like a pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatternError {
+    /// The pattern is malformed; `start..end` is the (byte) range of the pattern at fault.
+    Syntax {
+        start: usize,
+        end: usize,
+        message: String,
+    },
+    /// Something else went wrong, like the cache failing to build.
+    Apt(AptError),
+}
+
+#[cfg(not(feature = "ye-olde-apt"))]
+impl PatternError {
+    /// apt formats its syntax errors like `input:3-8: error: expected )`, then draws a picture.
+    pub(crate) fn from_apt(err: AptError) -> PatternError {
+        for message in &err.messages {
+            let first_line = message.lines().next().unwrap_or("");
+            let parsed = first_line.strip_prefix("input:").and_then(|rest| {
+                let (range, message) = rest.split_once(": error: ")?;
+                let (start, end) = range.split_once('-')?;
+                Some((start.parse().ok()?, end.parse().ok()?, message))
+            });
+
+            if let Some((start, end, message)) = parsed {
+                return PatternError::Syntax {
+                    start,
+                    end,
+                    message: message.to_string(),
+                };
+            }
+        }
+
+        PatternError::Apt(err)
+    }
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatternError::Syntax {
+                start,
+                end,
+                ref message,
+            } => write!(f, "pattern error at {start}-{end}: {message}"),
+            PatternError::Apt(ref err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl error::Error for PatternError {}
+
+/// A control file (or similar) which isn't valid deb822.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deb822Error {
+    /// Counting from one.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Deb822Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for Deb822Error {}
+
+/// An `Origins-Pattern` entry which couldn't be understood.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OriginPatternError {
+    pub pattern: String,
+    pub message: String,
+}
+
+impl fmt::Display for OriginPatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "origin pattern {:?}: {}", self.pattern, self.message)
+    }
+}
+
+impl error::Error for OriginPatternError {}
@@ -0,0 +1,477 @@
+//! Drawing the dependency graph, like `apt-cache dotty`, as Graphviz DOT or GraphML.
+//!
+//! Nodes are packages, each shown with the version picked by `GraphOpts::versions`.
+//! Alternatives (`a | b`) hang off a small node of their own, so they're drawn together.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::io;
+use std::io::Write;
+
+use crate::Cache;
+use crate::closure::VersionChoice;
+use crate::sane::PackageId;
+use crate::sane::PkgView;
+
+/// What to walk, and what to leave out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphOpts {
+    /// Dependencies to follow, as in `DepView::dep_type()`.
+    pub dep_types: Vec<String>,
+    /// How many dependencies away from the roots to go; `None` for everything.
+    pub max_depth: Option<usize>,
+    /// Only include packages in these sections, e.g. `libs` or `non-free/libs`, if any.
+    pub sections: Vec<String>,
+    /// Only include packages with these priorities, e.g. `required`, if any.
+    pub priorities: Vec<String>,
+    pub versions: VersionChoice,
+}
+
+impl Default for GraphOpts {
+    fn default() -> GraphOpts {
+        GraphOpts {
+            dep_types: ["PreDepends", "Depends", "Recommends"]
+                .iter()
+                .map(|dep_type| dep_type.to_string())
+                .collect(),
+            max_depth: None,
+            sections: Vec::new(),
+            priorities: Vec::new(),
+            versions: VersionChoice::default(),
+        }
+    }
+}
+
+/// A package in the graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub id: PackageId,
+    pub name: String,
+    pub arch: String,
+    /// `None` for virtual packages, and those with no version to pick.
+    pub version: Option<String>,
+    pub section: Option<String>,
+    pub priority: Option<String>,
+    /// How many dependencies away from a root this was first found.
+    pub depth: usize,
+}
+
+/// A dependency between two packages, or a `Provides`, from the provider to the virtual package.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub from: PackageId,
+    pub to: PackageId,
+    /// As in `DepView::dep_type()`, or `Provides`.
+    pub dep_type: String,
+    pub comp_type: Option<String>,
+    pub target_ver: Option<String>,
+    /// Set for alternatives: every edge of a `a | b` dependency has the same group.
+    pub or_group: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    by_id: HashMap<PackageId, usize>,
+}
+
+impl Graph {
+    /// Everything reachable from these packages (`name` or `name:arch`). Unknown names are
+    /// ignored. The roots are included even if the filters would leave them out.
+    pub fn from_roots(cache: &mut Cache, roots: &[&str], opts: &GraphOpts) -> Graph {
+        let mut ids = Vec::new();
+        for name in roots {
+            let mut found = match name.split_once(':') {
+                Some((name, arch)) => cache.find_by_name_arch(name, arch),
+                None => cache.find_by_name(name),
+            };
+            if let Some(pkg) = found.next() {
+                ids.push(pkg.id());
+            }
+        }
+
+        Graph::walk(cache, ids, opts)
+    }
+
+    /// Everything installed, and what that reaches.
+    pub fn installed(cache: &mut Cache, opts: &GraphOpts) -> Graph {
+        let ids = cache
+            .iter()
+            .filter_map(|pkg| pkg.current_version().map(|_| pkg.id()))
+            .collect();
+
+        Graph::walk(cache, ids, opts)
+    }
+
+    fn walk(cache: &mut Cache, roots: Vec<PackageId>, opts: &GraphOpts) -> Graph {
+        let mut graph = Graph::default();
+        let mut excluded = HashSet::new();
+        let mut queue = VecDeque::new();
+        let mut or_groups = 0;
+
+        for id in roots {
+            let mut found = cache.package_by_id(id);
+            let pkg = found.next().expect("roots are in the cache");
+            if graph.add(node(&pkg, 0, opts)) {
+                queue.push_back(id);
+            }
+        }
+
+        while let Some(id) = queue.pop_front() {
+            let depth = graph.node(id).expect("queued nodes exist").depth;
+            if opts.max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+
+            let mut found = cache.package_by_id(id);
+            let pkg = found.next().expect("queued packages are in the cache");
+            let wanted = chosen(&pkg, opts.versions);
+
+            // see where the target leads, unless it's filtered out
+            let mut reach = |target: &PkgView, graph: &mut Graph| -> bool {
+                let target_id = target.id();
+                if graph.by_id.contains_key(&target_id) {
+                    return true;
+                }
+                if excluded.contains(&target_id) {
+                    return false;
+                }
+
+                let node = node(target, depth + 1, opts);
+                if !included(&node, opts) {
+                    excluded.insert(target_id);
+                    return false;
+                }
+
+                graph.add(node);
+                queue.push_back(target_id);
+                true
+            };
+
+            let wanted = match wanted {
+                Some(wanted) => wanted,
+                None => {
+                    // a virtual package leads to whatever provides it
+                    let mut providers = pkg.provided_by();
+                    while let Some(prv) = providers.next() {
+                        let owner = prv.owner_pkg();
+                        if chosen(&owner, opts.versions) != Some(prv.owner_version()) {
+                            continue;
+                        }
+
+                        if reach(&owner, &mut graph) {
+                            let provide_version = prv.provide_version();
+                            graph.edges.push(Edge {
+                                from: owner.id(),
+                                to: id,
+                                dep_type: "Provides".to_string(),
+                                comp_type: provide_version.as_ref().map(|_| "=".to_string()),
+                                target_ver: provide_version,
+                                or_group: None,
+                            });
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            let mut vers = pkg.versions();
+            while let Some(ver) = vers.next() {
+                if ver.version() != wanted {
+                    continue;
+                }
+
+                let mut in_group = false;
+                let mut deps = ver.dep_iter();
+                while let Some(dep) = deps.next() {
+                    let dep_type = dep.dep_type();
+                    let group = if dep.is_or() || in_group {
+                        if !in_group {
+                            or_groups += 1;
+                        }
+                        Some(or_groups)
+                    } else {
+                        None
+                    };
+                    in_group = dep.is_or();
+
+                    if !opts.dep_types.contains(&dep_type) || !reach(&dep.target_pkg(), &mut graph)
+                    {
+                        continue;
+                    }
+
+                    let comp_type = dep.comp_type();
+                    let (comp_type, target_ver) = if comp_type.is_empty() {
+                        (None, None)
+                    } else {
+                        (Some(comp_type), Some(dep.target_ver()))
+                    };
+
+                    graph.edges.push(Edge {
+                        from: id,
+                        to: dep.target_pkg().id(),
+                        dep_type,
+                        comp_type,
+                        target_ver,
+                        or_group: group,
+                    });
+                }
+            }
+        }
+
+        graph
+    }
+
+    fn add(&mut self, node: Node) -> bool {
+        if self.by_id.contains_key(&node.id) {
+            return false;
+        }
+        self.by_id.insert(node.id, self.nodes.len());
+        self.nodes.push(node);
+        true
+    }
+
+    /// In the order they were found: the roots, then breadth first.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn node(&self, id: PackageId) -> Option<&Node> {
+        self.by_id.get(&id).map(|&idx| &self.nodes[idx])
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Graphviz's format, for `dot -Tsvg`.
+    pub fn write_dot<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "digraph packages {{")?;
+        writeln!(out, "    node [shape=box];")?;
+
+        for node in &self.nodes {
+            let label = match node.version {
+                Some(ref version) => format!("{}:{}\n{}", node.name, node.arch, version),
+                None => format!("{}:{}", node.name, node.arch),
+            };
+            let shape = if node.version.is_some() {
+                "box"
+            } else {
+                "ellipse"
+            };
+            writeln!(
+                out,
+                "    p{} [label={}, shape={shape}];",
+                node.id.0,
+                dot_quote(&label)
+            )?;
+        }
+
+        let mut drawn_groups = HashSet::new();
+        for edge in &self.edges {
+            let (style, colour) = style(&edge.dep_type);
+            let attrs = format!("style={style}, color={colour}");
+            let label = constraint(edge)
+                .map(|label| format!(", label={}", dot_quote(&label)))
+                .unwrap_or_default();
+
+            match edge.or_group {
+                Some(group) => {
+                    if drawn_groups.insert(group) {
+                        writeln!(out, "    or{group} [shape=point];")?;
+                        writeln!(out, "    p{} -> or{group} [{attrs}];", edge.from.0)?;
+                    }
+                    writeln!(out, "    or{group} -> p{} [{attrs}{label}];", edge.to.0)?;
+                }
+                None => writeln!(
+                    out,
+                    "    p{} -> p{} [{attrs}{label}];",
+                    edge.from.0, edge.to.0
+                )?,
+            }
+        }
+
+        writeln!(out, "}}")
+    }
+
+    /// GraphML, for yEd, Gephi, networkx, ... Or-groups are nodes with a `kind` of `or`.
+    pub fn write_graphml<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            out,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        for key in ["kind", "name", "arch", "version", "section", "priority"] {
+            writeln!(
+                out,
+                r#"  <key id="{key}" for="node" attr.name="{key}" attr.type="string"/>"#
+            )?;
+        }
+        for key in ["dep_type", "constraint"] {
+            writeln!(
+                out,
+                r#"  <key id="{key}" for="edge" attr.name="{key}" attr.type="string"/>"#
+            )?;
+        }
+        writeln!(out, r#"  <graph id="packages" edgedefault="directed">"#)?;
+
+        for node in &self.nodes {
+            writeln!(out, r#"    <node id="p{}">"#, node.id.0)?;
+            let kind = if node.version.is_some() {
+                "package"
+            } else {
+                "virtual"
+            };
+            let fields = [
+                ("kind", Some(kind)),
+                ("name", Some(node.name.as_str())),
+                ("arch", Some(node.arch.as_str())),
+                ("version", node.version.as_deref()),
+                ("section", node.section.as_deref()),
+                ("priority", node.priority.as_deref()),
+            ];
+            for (key, value) in fields {
+                if let Some(value) = value {
+                    writeln!(
+                        out,
+                        r#"      <data key="{key}">{}</data>"#,
+                        xml_escape(value)
+                    )?;
+                }
+            }
+            writeln!(out, "    </node>")?;
+        }
+
+        let mut drawn_groups = HashSet::new();
+        for (idx, edge) in self.edges.iter().enumerate() {
+            let dep_type = xml_escape(&edge.dep_type);
+            let from = match edge.or_group {
+                Some(group) => {
+                    if drawn_groups.insert(group) {
+                        writeln!(out, r#"    <node id="or{group}">"#)?;
+                        writeln!(out, r#"      <data key="kind">or</data>"#)?;
+                        writeln!(out, "    </node>")?;
+                        writeln!(
+                            out,
+                            r#"    <edge id="g{group}" source="p{}" target="or{group}">"#,
+                            edge.from.0
+                        )?;
+                        writeln!(out, r#"      <data key="dep_type">{dep_type}</data>"#)?;
+                        writeln!(out, "    </edge>")?;
+                    }
+                    format!("or{group}")
+                }
+                None => format!("p{}", edge.from.0),
+            };
+
+            writeln!(
+                out,
+                r#"    <edge id="e{idx}" source="{from}" target="p{}">"#,
+                edge.to.0
+            )?;
+            writeln!(out, r#"      <data key="dep_type">{dep_type}</data>"#)?;
+            if let Some(constraint) = constraint(edge) {
+                writeln!(
+                    out,
+                    r#"      <data key="constraint">{}</data>"#,
+                    xml_escape(&constraint)
+                )?;
+            }
+            writeln!(out, "    </edge>")?;
+        }
+
+        writeln!(out, "  </graph>")?;
+        writeln!(out, "</graphml>")
+    }
+}
+
+/// The version of the package to walk from, if it has one.
+fn chosen(pkg: &PkgView, versions: VersionChoice) -> Option<String> {
+    match versions {
+        VersionChoice::Candidate => pkg.candidate_version(),
+        VersionChoice::Installed => pkg.current_version().or_else(|| pkg.candidate_version()),
+    }
+}
+
+fn node(pkg: &PkgView, depth: usize, opts: &GraphOpts) -> Node {
+    let version = chosen(pkg, opts.versions);
+    let (mut section, mut priority) = (None, None);
+    if let Some(ref wanted) = version {
+        let mut vers = pkg.versions();
+        while let Some(ver) = vers.next() {
+            if ver.version() == *wanted {
+                section = ver.section();
+                priority = ver.priority_type();
+                break;
+            }
+        }
+    }
+
+    Node {
+        id: pkg.id(),
+        name: pkg.name(),
+        arch: pkg.arch(),
+        version,
+        section,
+        priority,
+        depth,
+    }
+}
+
+/// Virtual packages have no section or priority, so are always included.
+fn included(node: &Node, opts: &GraphOpts) -> bool {
+    if node.version.is_none() {
+        return true;
+    }
+
+    let matches = |wanted: &[String], value: &Option<String>| {
+        wanted.is_empty() || value.as_ref().is_some_and(|value| wanted.contains(value))
+    };
+    matches(&opts.sections, &node.section) && matches(&opts.priorities, &node.priority)
+}
+
+/// Roughly `apt-cache dotty`'s colours: hard dependencies solid, soft ones broken,
+/// negative ones red.
+fn style(dep_type: &str) -> (&'static str, &'static str) {
+    match dep_type {
+        "PreDepends" => ("bold", "black"),
+        "Depends" => ("solid", "black"),
+        "Recommends" => ("dashed", "black"),
+        "Suggests" | "Enhances" => ("dotted", "gray"),
+        "Conflicts" | "Breaks" => ("solid", "red"),
+        "Replaces" | "Obsoletes" => ("dashed", "red"),
+        "Provides" => ("dashed", "blue"),
+        _ => ("solid", "gray"),
+    }
+}
+
+fn constraint(edge: &Edge) -> Option<String> {
+    match (&edge.comp_type, &edge.target_ver) {
+        (Some(comp_type), Some(target_ver)) => Some(format!("{comp_type} {target_ver}")),
+        _ => None,
+    }
+}
+
+fn dot_quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -31,12 +31,30 @@
 //! `libapt-pkg` also just segfaults if you do anything wrong, or re-use anything at the wrong time,
 //! or etc. I've tried to hide this, but I advise you not to push or outsmart the borrow checker.
 
+pub mod changelog;
 mod citer;
+pub mod closure;
+pub mod copyright;
+pub mod deb822;
+pub mod debfile;
+pub mod diff;
+pub mod dpkg;
+pub mod error;
+pub mod graph;
 mod raw;
 pub mod sane;
+#[cfg(feature = "serde")]
+pub mod sbom;
+#[cfg(all(feature = "serde", not(feature = "ye-olde-apt")))]
+pub mod security;
 pub mod simple;
+pub mod snapshot;
+pub mod transaction;
+pub mod upgrade;
+pub mod why;
 
 pub use crate::sane::Cache;
+pub use crate::sane::CacheBuilder;
 
 #[cfg(test)]
 mod tests {
@@ -83,7 +101,851 @@ mod tests {
         let mut cache = Cache::get_singleton();
         cache.reload();
         cache.reload();
-        cache.reload();
-        cache.reload();
+        cache.try_reload().unwrap();
+        cache.try_reload().unwrap();
+    }
+
+    #[test]
+    fn search_packages() {
+        use crate::sane::SearchOpts;
+
+        let mut cache = Cache::get_singleton();
+
+        let names_only = SearchOpts {
+            names_only: true,
+            ..SearchOpts::default()
+        };
+        let found: Vec<String> = cache
+            .search("^apt$", names_only)
+            .unwrap()
+            .map(|p| p.name())
+            .collect();
+        assert_eq!(vec!["apt"], found, "once, whatever the architectures");
+
+        assert!(
+            cache
+                .search("PACKAGE MANAGER", SearchOpts::default())
+                .unwrap()
+                .any(|p| p.name() == "apt")
+        );
+
+        let full = SearchOpts {
+            full: true,
+            ..SearchOpts::default()
+        };
+        assert!(
+            cache
+                .search("Priority: (required|important)", full)
+                .unwrap()
+                .any(|p| p.name() == "apt")
+        );
+
+        assert!(cache.search("(", SearchOpts::default()).is_err());
+    }
+
+    #[test]
+    fn find_by_patterns() {
+        let mut cache = Cache::get_singleton();
+
+        let globbed: Vec<String> = cache.find_by_glob("ap?").map(|p| p.name()).collect();
+        assert!(globbed.contains(&"apt".to_string()));
+        assert!(globbed.iter().all(|name| name.len() == 3));
+
+        assert!(
+            cache
+                .find_by_regex("^ap+t$")
+                .unwrap()
+                .any(|p| p.name() == "apt")
+        );
+        assert!(cache.find_by_regex("[").is_err());
+    }
+
+    #[cfg(not(feature = "ye-olde-apt"))]
+    #[test]
+    fn select_patterns() {
+        use crate::error::PatternError;
+
+        let mut cache = Cache::get_singleton();
+
+        match cache.select("?name(^apt$)") {
+            Ok(found) => assert!(found.any(|p| p.name() == "apt")),
+            Err(PatternError::Apt(ref e)) if e.to_string().contains("need apt 1.9") => return,
+            Err(other) => panic!("unexpected error: {other}"),
+        }
+
+        assert!(
+            cache
+                .select("?and(?installed, ?name(^apt$))")
+                .unwrap()
+                .all(|p| p.current_version().is_some())
+        );
+
+        match cache.select("?and(?installed,") {
+            Err(PatternError::Syntax { start, end, .. }) => assert!(start <= end && end <= 16),
+            Err(other) => panic!("unexpected error: {other}"),
+            Ok(_) => panic!("pattern shouldn't parse"),
+        }
+    }
+
+    #[test]
+    fn groups() {
+        use crate::sane::MultiArch;
+
+        let mut cache = Cache::get_singleton();
+
+        let mut groups = cache.groups();
+        let mut seen_apt = false;
+        while let Some(group) = groups.next() {
+            if group.name() != "apt" {
+                continue;
+            }
+            seen_apt = true;
+
+            assert!(group.packages().all(|p| p.name() == "apt"));
+            let preferred = group.find_preferred_pkg().expect("apt is a real package");
+            assert_eq!("apt", preferred.name());
+        }
+        assert!(seen_apt);
+        drop(groups);
+
+        let mut libc = cache.find_by_name("libc6");
+        if let Some(pkg) = libc.next() {
+            assert!(pkg.versions().all(|v| v.multi_arch() == MultiArch::Same));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut cache = Cache::get_singleton();
+        let mut found = cache.find_by_name("dpkg");
+        let pkg = found.next().expect("dpkg is installed");
+        let details = simple::PackageDetails::new(&pkg);
+        assert!(!details.versions.is_empty());
+        assert!(
+            details.versions[0]
+                .dependencies
+                .iter()
+                .all(|dep| dep.comp_type.is_some() == dep.target_ver.is_some())
+        );
+
+        let json = serde_json::to_string(&details).unwrap();
+        let back: simple::PackageDetails = serde_json::from_str(&json).unwrap();
+        assert_eq!(details.pkg.name, back.pkg.name);
+        assert_eq!(details.versions.len(), back.versions.len());
+        assert_eq!(
+            details.versions[0].dependencies.len(),
+            back.versions[0].dependencies.len()
+        );
+    }
+
+    #[test]
+    fn snapshot() {
+        fn assert_send<T: Send + Sync>(_: &T) {}
+
+        let snap = {
+            let mut cache = Cache::get_singleton();
+            snapshot::Snapshot::new(&mut cache)
+        };
+        assert_send(&snap);
+
+        let dpkg = snap
+            .find_by_name("dpkg")
+            .first()
+            .copied()
+            .expect("dpkg is installed");
+        let current = snap.package(dpkg).current.expect("dpkg is installed");
+        assert_eq!(dpkg, snap.version(current).package);
+        assert!(
+            snap.files(current)
+                .any(|(_, file)| file.file_name.ends_with("status"))
+        );
+
+        for dep in snap.dependencies(current) {
+            assert_eq!(current, dep.version);
+            assert!(
+                snap.reverse_dependencies(dep.target)
+                    .any(|rdep| rdep.version == current)
+            );
+        }
+
+        for (pkg, _) in snap.packages().take(1000) {
+            for prv in snap.provided_by(pkg) {
+                assert_eq!(pkg, prv.target);
+                assert!(snap.provides(prv.version).iter().any(|p| p.target == pkg));
+            }
+        }
+    }
+
+    #[test]
+    fn deb822() {
+        let paragraphs = deb822::parse(
+            "# a comment\n\
+             Package: foo\n\
+             Description: short\n \
+             long\n \
+             .\n \
+             more\n\
+             \n\
+             \n\
+             package: bar\n",
+        )
+        .unwrap();
+        assert_eq!(2, paragraphs.len());
+        assert_eq!(2, paragraphs[0].line);
+        assert_eq!(Some("foo"), paragraphs[0].get("package"));
+        assert_eq!(
+            Some("short\nlong\n.\nmore"),
+            paragraphs[0].get("Description")
+        );
+        assert_eq!(9, paragraphs[1].line);
+        assert_eq!(Some("bar"), paragraphs[1].get("Package"));
+
+        assert_eq!(
+            3,
+            deb822::parse("Package: foo\n no colon\noops\n")
+                .unwrap_err()
+                .line
+        );
+        assert_eq!(1, deb822::parse(" continued\n").unwrap_err().line);
+    }
+
+    #[test]
+    fn diff_states() {
+        use std::fs;
+
+        let dir = std::env::temp_dir().join(format!("apt-pkg-native-diff-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("old");
+        let new = dir.join("new");
+        let status = |name: &str, version: &str, state: &str| {
+            format!(
+                "Package: {name}\nStatus: install ok {state}\nArchitecture: all\nVersion: {version}\n\n"
+            )
+        };
+        fs::write(
+            &old,
+            status("kept", "1.0", "installed")
+                + &status("gone", "1.0", "installed")
+                + &status("newer", "1.0", "installed")
+                + &status("older", "2.0", "installed")
+                + &status("purged", "1.0", "config-files"),
+        )
+        .unwrap();
+        fs::write(
+            &new,
+            status("kept", "1.0", "installed")
+                + &status("newer", "1.0+b1", "installed")
+                + &status("older", "2.0~rc1", "installed")
+                + &status("fresh", "3", "installed"),
+        )
+        .unwrap();
+
+        let old = diff::PackageState::from_status_file(&old).unwrap();
+        let new = diff::PackageState::from_status_file(&new).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(old.get("purged", "all").is_none());
+
+        let cache = Cache::get_singleton();
+        let diff = old.diff(&new, &cache);
+        let names =
+            |list: &[diff::Changed]| list.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            vec!["fresh"],
+            diff.added.iter().map(|i| &i.name).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["gone"],
+            diff.removed.iter().map(|i| &i.name).collect::<Vec<_>>()
+        );
+        assert_eq!(vec!["newer"], names(&diff.upgraded));
+        assert_eq!(vec!["older"], names(&diff.downgraded));
+        assert!(diff.origin_changed.is_empty());
+        assert_eq!(
+            "+ fresh:all 3\n- gone:all 1.0\n^ newer:all 1.0 -> 1.0+b1\nv older:all 2.0 -> 2.0~rc1\n",
+            diff.to_string()
+        );
+        assert!(new.diff(&new, &cache).is_empty());
+    }
+
+    #[test]
+    fn diff_cache_against_status() {
+        let on_disk = diff::PackageState::from_status_file("/var/lib/dpkg/status").unwrap();
+        let mut cache = Cache::get_singleton();
+        let from_cache = diff::PackageState::from_cache(&mut cache);
+        let snap = snapshot::Snapshot::new(&mut cache);
+        let from_snapshot = diff::PackageState::from_snapshot(&snap);
+
+        assert!(on_disk.packages().count() > 2);
+        assert!(on_disk.diff(&from_cache, &cache).is_empty());
+        assert_eq!(from_cache, from_snapshot);
+    }
+
+    #[test]
+    fn installed_record() {
+        use dpkg::*;
+
+        let record = InstalledRecord::parse(
+            "Package: foo\n\
+             Status: hold ok half-configured\n\
+             Installed-Size: 42\n\
+             Architecture: amd64\n\
+             Version: 1.2-3\n\
+             Config-Version: 1.2-2\n\
+             Conffiles:\n \
+             /etc/foo.conf 0123456789abcdef0123456789abcdef\n \
+             /etc/old.conf 0123456789abcdef0123456789abcdef obsolete\n",
+        )
+        .unwrap();
+        assert_eq!("foo", record.package);
+        assert_eq!(
+            Status {
+                want: Want::Hold,
+                flag: Flag::Ok,
+                state: State::HalfConfigured
+            },
+            record.status
+        );
+        assert_eq!(Some(42), record.installed_size);
+        assert_eq!(Some("1.2-2"), record.config_version.as_deref());
+        assert_eq!(2, record.conffiles.len());
+        assert_eq!("/etc/foo.conf", record.conffiles[0].path);
+        assert!(!record.conffiles[0].obsolete);
+        assert!(record.conffiles[1].obsolete);
+
+        assert!(InstalledRecord::parse("Package: foo\nStatus: install ok\n").is_err());
+
+        let mut cache = Cache::get_singleton();
+        let mut found = cache.find_by_name("dpkg");
+        let pkg = found.next().expect("dpkg is installed");
+        let record = pkg.installed_record().unwrap().expect("dpkg is installed");
+        assert_eq!("dpkg", record.package);
+        assert_eq!(pkg.current_version(), Some(record.version));
+        assert_eq!(State::Installed, record.status.state);
+        assert!(record.installed_size.is_some());
+        assert!(!record.conffiles.is_empty());
+        for conffile in &record.conffiles {
+            assert!(conffile.path.starts_with('/'));
+            assert!(conffile.md5 == "newconffile" || conffile.md5.len() == 32);
+        }
+    }
+
+    #[test]
+    fn installed_files() {
+        let mut cache = Cache::get_singleton();
+        assert!(
+            cache
+                .package_owning("/usr/bin/dpkg")
+                .unwrap()
+                .contains(&"dpkg".to_string())
+        );
+        assert!(cache.package_owning("/no/such/file").unwrap().is_empty());
+
+        let mut found = cache.find_by_name("dpkg");
+        let pkg = found.next().expect("dpkg is installed");
+        let files = pkg.installed_files().unwrap();
+        assert!(files.contains(&std::path::PathBuf::from("/usr/bin/dpkg")));
+
+        // images often drop docs, so only the important bits are reliably there
+        let checks = pkg.verify().unwrap();
+        let dpkg = checks
+            .iter()
+            .find(|check| check.path == std::path::Path::new("/usr/bin/dpkg"))
+            .expect("dpkg has md5sums");
+        assert_eq!(dpkg::FileStatus::Ok, dpkg.status);
+    }
+
+    #[test]
+    fn translated_description() {
+        let mut cache = Cache::get_singleton();
+        let mut found = cache.find_by_name("dpkg");
+        let pkg = found.next().expect("dpkg is installed");
+        let mut versions = pkg.versions();
+        let ver = versions.next().expect("dpkg has a version");
+
+        let shown = ver
+            .translated_description(None)
+            .expect("everything has a description");
+        assert!(!shown.short_desc.is_empty());
+        assert_eq!(32, shown.md5.len());
+
+        let picked = ver.translated_description(Some(&shown.language));
+        assert_eq!(Some(&shown), picked.as_ref());
+        assert!(ver.translated_description(Some("tlh")).is_none());
+    }
+
+    #[test]
+    fn fetch_changelog() {
+        let dir = std::env::temp_dir().join(format!("apt-pkg-native-serve-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("pool/main/f/foo")).unwrap();
+        let log = dir.join("pool/main/f/foo/foo_1.0_changelog");
+        std::fs::write(&log, "foo (1.0) unstable; urgency=medium\n").unwrap();
+
+        let fetched = changelog::fetch(&format!("file://{}", log.display())).unwrap();
+        assert!(fetched.starts_with("foo (1.0)"));
+
+        let missing = format!("file://{}", dir.join("nope").display());
+        assert!(changelog::fetch(&missing).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn download_details() {
+        let mut cache = Cache::get_singleton();
+        let mut found = cache.find_by_name("dpkg");
+        let pkg = found.next().expect("dpkg is installed");
+        let mut versions = pkg.versions();
+        while let Some(ver) = versions.next() {
+            assert!(ver.installed_size() > 0);
+
+            // only versions from a package list have these, and there may not be any lists
+            match ver.file_name() {
+                Some(file_name) => {
+                    assert!(file_name.ends_with(".deb"));
+                    assert!(ver.size() > 0);
+                    assert!(ver.download_uri().unwrap().ends_with(&file_name));
+                    #[cfg(not(feature = "ye-olde-apt"))]
+                    assert!(ver.hashes().iter().any(|hash| hash.kind == "SHA256"));
+                }
+                None => assert!(ver.download_uri().is_none()),
+            }
+        }
+    }
+
+    #[test]
+    fn ids() {
+        use crate::sane::{PackageId, VersionId};
+        use std::collections::HashSet;
+
+        let mut cache = Cache::get_singleton();
+        let (pkg_id, ver_id, name, version) = {
+            let mut found = cache.find_by_name("dpkg");
+            let pkg = found.next().expect("dpkg is installed");
+            let mut versions = pkg.versions();
+            let ver = versions.next().expect("dpkg has a version");
+            let deps = ver.dep_iter().map(|dep| dep.id()).collect::<HashSet<_>>();
+            assert_eq!(deps.len(), ver.dep_iter().count());
+            (pkg.id(), ver.id(), pkg.name(), ver.version())
+        };
+
+        assert_eq!(
+            vec![name],
+            cache
+                .package_by_id(pkg_id)
+                .map(|pkg| pkg.name())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![version],
+            cache
+                .version_by_id(ver_id)
+                .map(|ver| ver.version())
+                .collect::<Vec<_>>()
+        );
+
+        let ids = cache.iter().map(|pkg| pkg.id()).collect::<HashSet<_>>();
+        assert_eq!(ids.len(), cache.iter().count());
+        assert!(cache.package_by_id(PackageId(u32::MAX)).next().is_none());
+        assert!(cache.version_by_id(VersionId(u32::MAX)).next().is_none());
+    }
+
+    #[test]
+    fn dependency_closure() {
+        use crate::closure::ClosureOpts;
+        use crate::sane::VersionId;
+
+        let mut cache = Cache::get_singleton();
+        let root = {
+            let mut found = cache.find_by_name("dpkg");
+            let pkg = found.next().expect("dpkg is installed");
+            let candidate = pkg.candidate_version().expect("dpkg has a candidate");
+            let mut versions = pkg.versions();
+            let mut root = None;
+            while let Some(ver) = versions.next() {
+                if ver.version() == candidate {
+                    root = Some(ver.id());
+                }
+            }
+            root.expect("the candidate is one of the versions")
+        };
+
+        let closure = cache.dependency_closure(&[root], ClosureOpts::default());
+        assert_eq!(root, closure.members()[0].id);
+        assert!(closure.members()[0].pulled_in_by.is_none());
+        assert!(
+            closure
+                .members()
+                .iter()
+                .any(|member| member.package == "tar")
+        );
+
+        for member in &closure.members()[1..] {
+            let edge = member
+                .pulled_in_by
+                .as_ref()
+                .expect("everything else was pulled in");
+            assert!(closure.contains(edge.from), "{edge}");
+            assert!(["Depends", "PreDepends"].contains(&edge.dep_type.as_str()));
+        }
+
+        let wider = cache.dependency_closure(
+            &[root],
+            ClosureOpts {
+                recommends: true,
+                suggests: true,
+                ..ClosureOpts::default()
+            },
+        );
+        assert!(wider.len() >= closure.len());
+        assert!(
+            cache
+                .dependency_closure(&[VersionId(u32::MAX)], ClosureOpts::default())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn why() {
+        let mut cache = Cache::get_singleton();
+        let chains = cache.why("libc6");
+        assert!(
+            !chains.is_empty(),
+            "something installed by hand needs libc6"
+        );
+
+        for chain in &chains {
+            if let Some(first) = chain.steps.first() {
+                let mut found = cache.find_by_name(&first.package.name);
+                let root = found.next();
+                assert!(root.is_none_or(|root| !root.is_auto_installed()), "{chain}");
+            }
+            for pair in chain.steps.windows(2) {
+                assert_ne!(pair[0].edge.from, pair[1].edge.from);
+            }
+        }
+
+        for conflict in cache.why_not("dpkg") {
+            assert!(["Conflicts", "Breaks"].contains(&conflict.edge.dep_type.as_str()));
+        }
+
+        assert!(cache.why("no-such-package").is_empty());
+        assert!(cache.why_not("no-such-package").is_empty());
+    }
+
+    #[test]
+    fn graph() {
+        use crate::graph::Graph;
+        use crate::graph::GraphOpts;
+
+        let mut cache = Cache::get_singleton();
+        let opts = GraphOpts {
+            max_depth: Some(1),
+            ..GraphOpts::default()
+        };
+        let graph = Graph::from_roots(&mut cache, &["dpkg"], &opts);
+
+        let root = &graph.nodes()[0];
+        assert_eq!("dpkg", root.name);
+        assert_eq!(0, root.depth);
+        assert!(graph.nodes().iter().all(|node| node.depth <= 1));
+        assert!(!graph.edges().is_empty());
+        for edge in graph.edges() {
+            assert!(graph.node(edge.from).is_some());
+            assert!(graph.node(edge.to).is_some());
+        }
+
+        let mut dot = Vec::new();
+        graph.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph packages {"));
+        assert!(dot.contains(&format!("p{} -> ", root.id.0)));
+
+        let mut graphml = Vec::new();
+        graph.write_graphml(&mut graphml).unwrap();
+        let graphml = String::from_utf8(graphml).unwrap();
+        assert_eq!(
+            graph.nodes().len(),
+            graphml.matches(r#"<data key="name">"#).count()
+        );
+
+        let filtered = Graph::from_roots(
+            &mut cache,
+            &["dpkg"],
+            &GraphOpts {
+                sections: vec!["no-such-section".to_string()],
+                ..GraphOpts::default()
+            },
+        );
+        assert_eq!(1, filtered.nodes().len());
+        assert!(filtered.edges().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn sbom() {
+        use crate::sbom::Sbom;
+
+        let mut cache = Cache::get_singleton();
+        let sbom = Sbom::installed(&mut cache);
+        let components = sbom.components();
+
+        let dpkg = components
+            .iter()
+            .find(|component| component.name == "dpkg")
+            .expect("dpkg is installed");
+        assert!(dpkg.purl.starts_with("pkg:deb/"), "{}", dpkg.purl);
+        assert!(dpkg.purl.contains("/dpkg@"), "{}", dpkg.purl);
+        assert!(dpkg.purl.contains(&format!("?arch={}", dpkg.arch)));
+        assert!(!dpkg.depends_on.is_empty());
+        for component in components {
+            assert!(
+                component
+                    .depends_on
+                    .iter()
+                    .all(|&dep| dep < components.len())
+            );
+        }
+
+        let spdx = serde_json::to_value(sbom.spdx("test")).unwrap();
+        assert_eq!("SPDX-2.3", spdx["spdxVersion"]);
+        assert_eq!("SPDXRef-DOCUMENT", spdx["SPDXID"]);
+        assert_eq!(components.len(), spdx["packages"].as_array().unwrap().len());
+        let created = spdx["creationInfo"]["created"].as_str().unwrap();
+        assert_eq!(20, created.len(), "{created}");
+        assert!(created.ends_with('Z'));
+        let namespace = serde_json::to_value(sbom.spdx("my image/a")).unwrap()["documentNamespace"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(
+            namespace.starts_with("https://spdx.org/spdxdocs/my%20image%2Fa-"),
+            "{namespace}"
+        );
+
+        let cyclonedx = serde_json::to_value(sbom.cyclonedx()).unwrap();
+        assert_eq!("CycloneDX", cyclonedx["bomFormat"]);
+        assert_eq!("1.5", cyclonedx["specVersion"]);
+        assert_eq!(
+            components.len(),
+            cyclonedx["dependencies"].as_array().unwrap().len()
+        );
+        assert_eq!(
+            dpkg.purl,
+            cyclonedx["components"][components
+                .iter()
+                .position(|component| component.name == "dpkg")
+                .unwrap()]["bom-ref"]
+        );
+    }
+
+    #[test]
+    fn copyright() {
+        let text = "Format: https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/\n\
+                    Upstream-Name: foo\n\
+                    \n\
+                    Files: *\n\
+                    Copyright: 2001 Someone\n\
+                    \x20          2002 Someone Else\n\
+                    License: GPL-2+ with OpenSSL exception\n\
+                    \n\
+                    Files: debian/*\n\
+                    \x20      lib/*.c\n\
+                    License: Expat or Apache-2.0\n\
+                    \n\
+                    Files: fonts/*\n\
+                    License: Made-Up\n\
+                    \n\
+                    License: Expat\n\
+                    \x20Permission is hereby granted...\n\
+                    \x20.\n\
+                    \x20THE SOFTWARE IS PROVIDED \"AS IS\"\n";
+        let parsed = copyright::parse(text).unwrap();
+        assert_eq!(Some("foo"), parsed.upstream_name.as_deref());
+        assert_eq!(3, parsed.files.len());
+        assert_eq!(vec!["debian/*", "lib/*.c"], parsed.files[1].patterns);
+        assert_eq!(
+            Some("2001 Someone\n2002 Someone Else"),
+            parsed.files[0].copyright.as_deref()
+        );
+        assert_eq!(
+            Some("Permission is hereby granted...\n\nTHE SOFTWARE IS PROVIDED \"AS IS\""),
+            parsed.licenses[0].text.as_deref()
+        );
+        assert_eq!(
+            Some(
+                "LicenseRef-GPL-2.0-or-later-with-OpenSSL-exception AND (MIT OR Apache-2.0) \
+                 AND LicenseRef-Made-Up"
+            ),
+            parsed.spdx_expression().as_deref()
+        );
+        let grouped = copyright::parse(
+            "Format: x\n\n\
+             Files: *\n\
+             Copyright: me\n\
+             License: GPL-2+ or Artistic, and BSD-3-clause\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Some("(GPL-2.0-or-later OR Artistic-1.0) AND BSD-3-Clause"),
+            grouped.spdx_expression().as_deref()
+        );
+
+        assert_eq!(
+            1,
+            copyright::parse("Files: *\nLicense: MIT\n")
+                .unwrap_err()
+                .line
+        );
+        assert_eq!(
+            3,
+            copyright::parse("Format: x\n\nFiles: *\nCopyright: me\n")
+                .unwrap_err()
+                .line
+        );
+
+        let mut cache = Cache::get_singleton();
+        let mut found = cache.find_by_name("dpkg");
+        let pkg = found.next().expect("dpkg is installed");
+        pkg.copyright().expect("readable, if not machine-readable");
+    }
+
+    #[test]
+    fn origin_pattern() {
+        use crate::simple::OriginPattern;
+
+        let pattern: OriginPattern = "o=Debian,codename=bookworm-security,label=Debian\\,Security"
+            .parse()
+            .unwrap();
+        assert_eq!(Some("Debian"), pattern.origin.as_deref());
+        assert_eq!(Some("bookworm-security"), pattern.codename.as_deref());
+        assert_eq!(Some("Debian,Security"), pattern.label.as_deref());
+        assert_eq!(None, pattern.archive);
+
+        assert!("origin=Debian,,".parse::<OriginPattern>().is_ok());
+        assert!("Debian".parse::<OriginPattern>().is_err());
+        assert!("colour=blue".parse::<OriginPattern>().is_err());
+    }
+
+    #[test]
+    fn upgradable() {
+        use crate::simple::OriginPattern;
+
+        let mut cache = Cache::get_singleton();
+        for upgrade in cache.upgradable(&[]) {
+            assert_ne!(upgrade.installed_version, upgrade.candidate_version);
+            assert!(!upgrade.security, "nothing matches no patterns");
+            assert!(!upgrade.held_back || upgrade.phased_update_percentage.is_some());
+        }
+
+        for upgrade in cache.upgradable(&[OriginPattern::default()]) {
+            assert_eq!(!upgrade.origins.is_empty(), upgrade.security);
+        }
+    }
+
+    #[cfg(not(feature = "ye-olde-apt"))]
+    #[test]
+    fn phasing() {
+        use crate::transaction::Action;
+        use crate::transaction::Phasing;
+
+        let mut cache = Cache::get_singleton();
+        {
+            let mut found = cache.find_by_name("dpkg");
+            let pkg = found.next().expect("dpkg is installed");
+            let mut vers = pkg.versions();
+            while let Some(ver) = vers.next() {
+                assert!(ver.is_phased_in(&Phasing::Ignore));
+            }
+        }
+
+        let upgradable = cache.upgradable(&[]);
+        {
+            let mut transaction = cache.transaction().unwrap();
+            assert!(transaction.upgrade(&Phasing::Ignore).unwrap().is_empty());
+        }
+
+        let mut transaction = cache.transaction().unwrap();
+        let machine_id = Phasing::MachineId("0123456789abcdef0123456789abcdef".to_string());
+        let held_back = transaction.upgrade(&machine_id).unwrap();
+        for name in &held_back {
+            assert!(upgradable.iter().any(|upgrade| {
+                &format!("{}:{}", upgrade.name, upgrade.arch) == name
+                    && upgrade.phased_update_percentage.is_some()
+            }));
+        }
+
+        for change in transaction.changes() {
+            assert!(
+                matches!(change.action, Action::Upgrade | Action::Downgrade),
+                "{change}"
+            );
+            assert!(!held_back.contains(&format!("{}:{}", change.name, change.arch)));
+        }
+    }
+
+    #[cfg(all(feature = "serde", not(feature = "ye-olde-apt")))]
+    #[test]
+    fn security() {
+        use crate::security::ScanOpts;
+        use crate::security::Tracker;
+
+        let mut cache = Cache::get_singleton();
+        let (source, source_version) = {
+            let mut found = cache.find_by_name("dpkg");
+            let pkg = found.next().expect("dpkg is installed");
+            let current = pkg.current_version().unwrap();
+            pkg.versions()
+                .filter_map(|ver| {
+                    if ver.version() == current {
+                        Some((ver.source_package(), ver.source_version()))
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .unwrap()
+        };
+
+        let resolved = |fixed: &str| {
+            serde_json::json!({
+                "status": "resolved",
+                "fixed_version": fixed,
+                "urgency": "low",
+                "repositories": {},
+            })
+        };
+        let tracker: Tracker = serde_json::from_value(serde_json::json!({
+            source.clone(): {
+                "CVE-0000-0001": { "releases": { "test": resolved("999:1") } },
+                "CVE-0000-0002": { "releases": { "test": resolved("0") } },
+                "CVE-0000-0003": { "releases": { "test": resolved(&source_version) } },
+                "CVE-0000-0004": { "releases": { "test": { "status": "open" } } },
+                "CVE-0000-0005": { "releases": { "other": resolved("999:1") } },
+            },
+            "no-such-source-package": {
+                "CVE-0000-0006": { "releases": { "test": { "status": "open" } } },
+            },
+        }))
+        .unwrap();
+
+        let findings = tracker.scan(&mut cache, &ScanOpts::new("test"));
+        let issues: Vec<_> = findings
+            .iter()
+            .map(|finding| finding.issue.as_str())
+            .collect();
+        assert_eq!(vec!["CVE-0000-0001", "CVE-0000-0004"], issues);
+
+        assert_eq!(source, findings[0].source);
+        assert_eq!(source_version, findings[0].source_version);
+        assert!(
+            findings[0]
+                .binaries
+                .iter()
+                .any(|name| name.starts_with("dpkg:"))
+        );
+        assert_eq!(Some("low"), findings[0].urgency.as_deref());
+        assert_eq!(Some("999:1"), findings[0].fixed_version.as_deref());
+        assert!(findings[0].fix.is_none(), "nothing is that new");
+        assert_eq!(None, findings[1].fixed_version);
     }
 }
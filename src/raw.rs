@@ -2,28 +2,63 @@
 ///  * `*mut c_void` are to be released by the appropriate function
 ///  * `*const c_chars` are short-term borrows
 ///  * `*mut c_chars` are to be freed by `libc::free`.
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
 use std::sync::Mutex;
+use std::sync::Once;
 
 use lazy_static::lazy_static;
 use libc::c_char;
 use libc::c_void;
 
+use crate::error::AptError;
+
 pub type PCache = *mut c_void;
 pub type PPkgIterator = *mut c_void;
+pub type PGrpIterator = *mut c_void;
 pub type PVerIterator = *mut c_void;
 pub type PDepIterator = *mut c_void;
+pub type PPrvIterator = *mut c_void;
 pub type PVerFileIterator = *mut c_void;
 pub type PPkgFileIterator = *mut c_void;
 pub type PVerFileParser = *mut c_void;
+pub type PDescription = *mut c_void;
+pub type PDebFile = *mut c_void;
+pub type PTransaction = *mut c_void;
+
+/// Called for each step of `transaction_commit`, with the `user_data` it was given.
+#[cfg(not(feature = "ye-olde-apt"))]
+pub type CommitCallback = unsafe extern "C" fn(
+    user_data: *mut c_void,
+    kind: i32,
+    package: *const c_char,
+    done: u32,
+    total: u32,
+    message: *const c_char,
+);
 
 #[link(name = "apt-pkg-c", kind = "static")]
 #[link(name = "apt-pkg")]
 unsafe extern "C" {
     /// Must be called exactly once, before anything else?
     fn init_config_system();
-    fn pkg_cache_create() -> PCache;
+    fn pkg_cache_create(deb_files: *const *const c_char, deb_file_count: usize) -> PCache;
     fn pkg_cache_release(cache: PCache);
 
+    // Configuration
+    // =============
+
+    pub fn config_native_arch() -> *mut c_char;
+    pub fn config_architectures() -> *mut c_char;
+    fn config_add_architectures(archs: *const *const c_char, count: usize);
+    fn config_set_root_dir(root: *const c_char);
+    pub fn config_find(name: *const c_char) -> *mut c_char;
+    pub fn config_find_file(name: *const c_char) -> *mut c_char;
+
+    /// Null (with an apt error) if the file can't be read.
+    pub fn file_md5(path: *const c_char) -> *mut c_char;
+
     pub fn pkg_cache_compare_versions(
         cache: PCache,
         left: *const c_char,
@@ -40,6 +75,18 @@ unsafe extern "C" {
         name: *const c_char,
         arch: *const c_char,
     ) -> PPkgIterator;
+    pub fn pkg_cache_find_glob(cache: PCache, glob: *const c_char) -> PPkgIterator;
+    pub fn pkg_cache_find_package_id(cache: PCache, id: u32) -> PPkgIterator;
+    pub fn pkg_cache_find_version_id(cache: PCache, id: u32) -> PVerIterator;
+    pub fn pkg_cache_find_regex(cache: PCache, regex: *const c_char) -> PPkgIterator;
+    pub fn pkg_cache_search(
+        cache: PCache,
+        regex: *const c_char,
+        names_only: bool,
+        full: bool,
+    ) -> PPkgIterator;
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn pkg_cache_select(cache: PCache, pattern: *const c_char) -> PPkgIterator;
     pub fn pkg_iter_release(iterator: PPkgIterator);
 
     pub fn pkg_iter_next(iterator: PPkgIterator);
@@ -48,10 +95,28 @@ unsafe extern "C" {
     // Package iterator accessors
     // ==========================
 
+    pub fn pkg_iter_id(iterator: PPkgIterator) -> u32;
     pub fn pkg_iter_name(iterator: PPkgIterator) -> *const c_char;
     pub fn pkg_iter_arch(iterator: PPkgIterator) -> *const c_char;
     pub fn pkg_iter_current_version(iterator: PPkgIterator) -> *const c_char;
     pub fn pkg_iter_candidate_version(iterator: PPkgIterator) -> *const c_char;
+    pub fn pkg_iter_is_auto(iterator: PPkgIterator) -> bool;
+
+    // Group iterators
+    // ===============
+
+    pub fn pkg_cache_grp_iter(cache: PCache) -> PGrpIterator;
+    pub fn grp_iter_release(iterator: PGrpIterator);
+
+    pub fn grp_iter_next(iterator: PGrpIterator);
+    pub fn grp_iter_end(iterator: PGrpIterator) -> bool;
+
+    // Group iterator accessors
+    // ========================
+
+    pub fn grp_iter_name(iterator: PGrpIterator) -> *const c_char;
+    pub fn grp_iter_pkg_iter(iterator: PGrpIterator) -> PPkgIterator;
+    pub fn grp_iter_find_preferred_pkg(iterator: PGrpIterator) -> PPkgIterator;
 
     // Version iterators
     // =================
@@ -65,6 +130,7 @@ unsafe extern "C" {
     // Version accessors
     // =================
 
+    pub fn ver_iter_id(iterator: PVerIterator) -> u32;
     pub fn ver_iter_version(iterator: PVerIterator) -> *mut c_char;
     pub fn ver_iter_section(iterator: PVerIterator) -> *mut c_char;
 
@@ -75,14 +141,53 @@ unsafe extern "C" {
     pub fn ver_iter_source_version(iterator: PVerIterator) -> *mut c_char;
     pub fn ver_iter_arch(iterator: PVerIterator) -> *mut c_char;
     pub fn ver_iter_priority_type(iterator: PVerIterator) -> *mut c_char;
+    pub fn ver_iter_multi_arch(iterator: PVerIterator) -> u8;
+    pub fn ver_iter_pkg(iterator: PVerIterator) -> PPkgIterator;
+
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn ver_iter_changelog_uri(iterator: PVerIterator) -> *mut c_char;
+
+    pub fn ver_iter_size(iterator: PVerIterator) -> u64;
+    pub fn ver_iter_installed_size(iterator: PVerIterator) -> u64;
+    pub fn ver_iter_file_name(iterator: PVerIterator) -> *mut c_char;
+    pub fn ver_iter_download_uri(iterator: PVerIterator) -> *mut c_char;
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn ver_iter_hashes(iterator: PVerIterator) -> *mut c_char;
+
+    pub fn fetch_file(
+        uri: *const c_char,
+        dest_dir: *const c_char,
+        dest_name: *const c_char,
+    ) -> bool;
+
+    /// Null if there's no such description; `lang` may be null for apt's choice.
+    pub fn ver_iter_translated_description(
+        iterator: PVerIterator,
+        lang: *const c_char,
+    ) -> PDescription;
+    pub fn description_release(description: PDescription);
+
+    pub fn description_language(description: PDescription) -> *mut c_char;
+    pub fn description_md5(description: PDescription) -> *mut c_char;
+    pub fn description_short_desc(description: PDescription) -> *mut c_char;
+    pub fn description_long_desc(description: PDescription) -> *mut c_char;
 
     #[cfg(not(feature = "ye-olde-apt"))]
     pub fn ver_iter_priority(iterator: PVerIterator) -> i32;
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn ver_iter_phased_update_percentage(iterator: PVerIterator) -> u8;
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn ver_iter_phased_in(
+        iterator: PVerIterator,
+        machine_id: *const c_char,
+        include_all: bool,
+    ) -> bool;
 
     // Dependency iterators
     // ====================
 
     pub fn ver_iter_dep_iter(iterator: PVerIterator) -> PDepIterator;
+    pub fn pkg_iter_rev_dep_iter(iterator: PPkgIterator) -> PDepIterator;
     pub fn dep_iter_release(iterator: PDepIterator);
 
     pub fn dep_iter_next(iterator: PDepIterator);
@@ -91,10 +196,32 @@ unsafe extern "C" {
     // Dependency accessors
     // ====================
 
+    pub fn dep_iter_id(iterator: PDepIterator) -> u32;
     pub fn dep_iter_target_pkg(iterator: PDepIterator) -> PPkgIterator;
     pub fn dep_iter_target_ver(iterator: PDepIterator) -> *const c_char;
     pub fn dep_iter_comp_type(iterator: PDepIterator) -> *const c_char;
     pub fn dep_iter_dep_type(iterator: PDepIterator) -> *const c_char;
+    pub fn dep_iter_is_or(iterator: PDepIterator) -> bool;
+    pub fn dep_iter_all_targets(iterator: PDepIterator) -> PVerIterator;
+    pub fn dep_iter_parent_ver(iterator: PDepIterator) -> PVerIterator;
+
+    // Provides iterators
+    // ==================
+
+    pub fn ver_iter_prv_iter(iterator: PVerIterator) -> PPrvIterator;
+    pub fn pkg_iter_prv_iter(iterator: PPkgIterator) -> PPrvIterator;
+    pub fn prv_iter_release(iterator: PPrvIterator);
+
+    pub fn prv_iter_next(iterator: PPrvIterator);
+    pub fn prv_iter_end(iterator: PPrvIterator) -> bool;
+
+    // Provides accessors
+    // ==================
+
+    pub fn prv_iter_target_pkg(iterator: PPrvIterator) -> PPkgIterator;
+    pub fn prv_iter_provide_version(iterator: PPrvIterator) -> *const c_char;
+    pub fn prv_iter_owner_pkg(iterator: PPrvIterator) -> PPkgIterator;
+    pub fn prv_iter_owner_version(iterator: PPrvIterator) -> *const c_char;
 
     pub fn ver_iter_ver_file_iter(iterator: PVerIterator) -> PVerFileIterator;
     pub fn ver_file_iter_release(iterator: PVerFileIterator);
@@ -103,10 +230,12 @@ unsafe extern "C" {
     pub fn ver_file_iter_end(iterator: PVerFileIterator) -> bool;
 
     pub fn ver_file_iter_get_parser(iterator: PVerFileIterator) -> PVerFileParser;
-    pub fn ver_file_parser_short_desc(parser: PVerFileParser) -> *const c_char;
-    pub fn ver_file_parser_long_desc(parser: PVerFileParser) -> *const c_char;
-    pub fn ver_file_parser_maintainer(parser: PVerFileParser) -> *const c_char;
-    pub fn ver_file_parser_homepage(parser: PVerFileParser) -> *const c_char;
+    pub fn ver_file_parser_release(parser: PVerFileParser);
+    pub fn ver_file_parser_short_desc(parser: PVerFileParser) -> *mut c_char;
+    pub fn ver_file_parser_long_desc(parser: PVerFileParser) -> *mut c_char;
+    pub fn ver_file_parser_maintainer(parser: PVerFileParser) -> *mut c_char;
+    pub fn ver_file_parser_homepage(parser: PVerFileParser) -> *mut c_char;
+    pub fn ver_file_parser_record(parser: PVerFileParser) -> *mut c_char;
 
     pub fn ver_file_iter_pkg_file_iter(iterator: PVerFileIterator) -> PPkgFileIterator;
     pub fn pkg_file_iter_release(iterator: PPkgFileIterator);
@@ -124,6 +253,96 @@ unsafe extern "C" {
     pub fn pkg_file_iter_component(iterator: PPkgFileIterator) -> *const c_char;
     pub fn pkg_file_iter_architecture(iterator: PPkgFileIterator) -> *const c_char;
     pub fn pkg_file_iter_index_type(iterator: PPkgFileIterator) -> *const c_char;
+
+    // Errors
+    // ======
+
+    pub fn apt_error_pending() -> bool;
+    pub fn apt_error_pop() -> *mut c_char;
+
+    // Transactions
+    // ============
+
+    pub fn transaction_create(cache: PCache) -> PTransaction;
+    pub fn transaction_release(transaction: PTransaction);
+
+    pub fn transaction_set_candidate(
+        transaction: PTransaction,
+        name: *const c_char,
+        version: *const c_char,
+    ) -> bool;
+    pub fn transaction_mark_install(transaction: PTransaction, name: *const c_char) -> bool;
+    pub fn transaction_mark_remove(
+        transaction: PTransaction,
+        name: *const c_char,
+        purge: bool,
+    ) -> bool;
+    pub fn transaction_resolve(transaction: PTransaction) -> bool;
+
+    pub fn transaction_broken_count(transaction: PTransaction) -> u32;
+    pub fn transaction_changes(transaction: PTransaction) -> usize;
+    pub fn transaction_change_name(transaction: PTransaction, idx: usize) -> *const c_char;
+    pub fn transaction_change_arch(transaction: PTransaction, idx: usize) -> *const c_char;
+    pub fn transaction_change_action(transaction: PTransaction, idx: usize) -> i32;
+    pub fn transaction_change_current_version(
+        transaction: PTransaction,
+        idx: usize,
+    ) -> *const c_char;
+    pub fn transaction_change_new_version(transaction: PTransaction, idx: usize) -> *const c_char;
+
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn transaction_upgrade(
+        transaction: PTransaction,
+        machine_id: *const c_char,
+        include_all: bool,
+    ) -> bool;
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn transaction_held_back(transaction: PTransaction) -> usize;
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn transaction_held_back_name(transaction: PTransaction, idx: usize) -> *const c_char;
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn transaction_held_back_arch(transaction: PTransaction, idx: usize) -> *const c_char;
+
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn transaction_commit(
+        transaction: PTransaction,
+        dry_run: bool,
+        callback: CommitCallback,
+        user_data: *mut c_void,
+    ) -> i32;
+
+    // Deb files
+    // =========
+
+    pub fn deb_file_open(path: *const c_char) -> PDebFile;
+    pub fn deb_file_release(deb: PDebFile);
+
+    pub fn deb_file_control(deb: PDebFile) -> *const c_char;
+    pub fn deb_file_control_field(deb: PDebFile, name: *const c_char) -> *mut c_char;
+    pub fn deb_file_control_member(
+        deb: PDebFile,
+        name: *const c_char,
+        len: *mut usize,
+    ) -> *mut c_char;
+
+    pub fn deb_file_list_data(deb: PDebFile) -> bool;
+    pub fn deb_file_data_count(deb: PDebFile) -> usize;
+    pub fn deb_file_data_name(deb: PDebFile, idx: usize) -> *const c_char;
+    pub fn deb_file_data_link_target(deb: PDebFile, idx: usize) -> *const c_char;
+    pub fn deb_file_data_type(deb: PDebFile, idx: usize) -> i32;
+    pub fn deb_file_data_mode(deb: PDebFile, idx: usize) -> u32;
+    pub fn deb_file_data_uid(deb: PDebFile, idx: usize) -> u32;
+    pub fn deb_file_data_gid(deb: PDebFile, idx: usize) -> u32;
+    pub fn deb_file_data_size(deb: PDebFile, idx: usize) -> u64;
+    pub fn deb_file_data_mtime(deb: PDebFile, idx: usize) -> u64;
+    pub fn deb_file_data_member(deb: PDebFile, name: *const c_char, len: *mut usize)
+    -> *mut c_char;
+}
+
+/// Set up apt's global configuration and system, if nobody has yet.
+pub fn init_config_system_once() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| unsafe { init_config_system() });
 }
 
 pub fn pkg_cache_get_singleton() -> &'static CACHE_SINGLETON {
@@ -133,27 +352,118 @@ pub fn pkg_cache_get_singleton() -> &'static CACHE_SINGLETON {
 #[derive(Debug)]
 pub struct CacheHolder {
     pub ptr: PCache,
+
+    /// What the cache was last built with, to be used again on every rebuild.
+    pub options: CacheOptions,
+}
+
+/// Settings applied every time the cache is built.
+#[derive(Clone, Debug, Default)]
+pub struct CacheOptions {
+    /// Local `.deb` files to add to the cache.
+    pub deb_files: Vec<CString>,
+
+    /// Architectures to add to `APT::Architectures`.
+    pub architectures: Vec<CString>,
+
+    /// Another system to look at, instead of `/`, as `RootDir`.
+    pub root: Option<CString>,
 }
 
 unsafe impl Send for CacheHolder {}
 
 impl CacheHolder {
-    pub fn re_up(&mut self) {
-        unsafe {
-            pkg_cache_release(self.ptr);
-            self.ptr = pkg_cache_create();
+    pub fn re_up(&mut self) -> Result<(), AptError> {
+        let options = self.options.clone();
+        self.rebuild(options)
+    }
+
+    /// Build a new cache with these settings. On failure, the old cache, and the
+    /// configuration it was built with, are left in place.
+    pub fn rebuild(&mut self, options: CacheOptions) -> Result<(), AptError> {
+        // Avoid pinning the architecture list unless someone has asked to change it.
+        let set_architectures =
+            !options.architectures.is_empty() || !self.options.architectures.is_empty();
+        if set_architectures {
+            let arch_ptrs = as_ptrs(&options.architectures);
+            unsafe { config_add_architectures(arch_ptrs.as_ptr(), arch_ptrs.len()) };
+        }
+
+        let set_root = options.root.is_some() || self.options.root.is_some();
+        if set_root {
+            set_root_dir(options.root.as_ref());
         }
+
+        let deb_file_ptrs = as_ptrs(&options.deb_files);
+        let ptr = unsafe { pkg_cache_create(deb_file_ptrs.as_ptr(), deb_file_ptrs.len()) };
+        if ptr.is_null() {
+            let err = AptError::take("couldn't build the cache");
+
+            // the old cache is staying, so the configuration has to match it again
+            if set_architectures {
+                let arch_ptrs = as_ptrs(&self.options.architectures);
+                unsafe { config_add_architectures(arch_ptrs.as_ptr(), arch_ptrs.len()) };
+            }
+            if set_root {
+                set_root_dir(self.options.root.as_ref());
+            }
+            return Err(err);
+        }
+
+        unsafe { pkg_cache_release(self.ptr) };
+        self.ptr = ptr;
+        self.options = options;
+        Ok(())
     }
 }
 
+fn set_root_dir(root: Option<&CString>) {
+    let root = root.map_or(std::ptr::null(), |root| root.as_ptr());
+    unsafe { config_set_root_dir(root) };
+}
+
+fn as_ptrs(strings: &[CString]) -> Vec<*const c_char> {
+    strings.iter().map(|s| s.as_ptr()).collect()
+}
+
 lazy_static! {
     #[derive(Debug)]
     pub static ref CACHE_SINGLETON: Mutex<CacheHolder> = {
+        init_config_system_once();
         unsafe {
-            init_config_system();
             Mutex::new(CacheHolder {
-                ptr: pkg_cache_create()
+                ptr: pkg_cache_create(std::ptr::null(), 0),
+                options: CacheOptions::default(),
             })
         }
     };
 }
+
+/// Copy a string `malloc`'d by the C side, then free it.
+pub unsafe fn make_owned_string_free(ptr: *mut libc::c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+
+    let string = unsafe { std::ffi::CStr::from_ptr(ptr) }
+        .to_string_lossy()
+        .into_owned();
+    unsafe { libc::free(ptr as *mut libc::c_void) };
+    Some(string)
+}
+
+/// Copy a buffer `malloc`'d by the C side, then free it.
+pub unsafe fn make_owned_bytes_free(ptr: *mut libc::c_char, len: usize) -> Option<Vec<u8>> {
+    if ptr.is_null() {
+        return None;
+    }
+
+    let bytes = unsafe { std::slice::from_raw_parts(ptr as *const u8, len) }.to_vec();
+    unsafe { libc::free(ptr as *mut libc::c_void) };
+    Some(bytes)
+}
+
+/// Paths are just bytes to apt.
+pub fn path_to_cstring(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes()).expect("paths can't contain nuls")
+}
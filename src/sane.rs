@@ -1,11 +1,31 @@
 use std::cmp;
 use std::ffi;
+use std::io;
 use std::marker::PhantomData;
+use std::path::Path;
+use std::path::PathBuf;
 use std::sync::MutexGuard;
 
 use crate::citer::CIterator;
 use crate::citer::RawIterator;
+use crate::closure::Closure;
+use crate::closure::ClosureOpts;
+use crate::copyright;
+use crate::copyright::Copyright;
+use crate::dpkg;
+use crate::dpkg::InstalledRecord;
+use crate::error::AptError;
+use crate::error::Deb822Error;
+#[cfg(not(feature = "ye-olde-apt"))]
+use crate::error::PatternError;
 use crate::raw;
+use crate::simple;
+use crate::simple::OriginPattern;
+#[cfg(not(feature = "ye-olde-apt"))]
+use crate::transaction::Phasing;
+use crate::transaction::Transaction;
+use crate::upgrade;
+use crate::why;
 
 /// A reference to the package cache singleton,
 /// from which most functionality can be accessed.
@@ -28,10 +48,27 @@ impl Cache {
     /// references to the cache at this point. Again, I remind you
     /// not to try and outsmart the borrow checker. It doesn't know
     /// how much trouble there is in here.
+    ///
+    /// Panics if apt can't build it again; see `try_reload()`.
     pub fn reload(&mut self) {
+        self.try_reload()
+            .expect("cache could be built before, but not now")
+    }
+
+    /// `reload()`, but if apt can't build the cache again, e.g. because the lists changed
+    /// underneath us, the previous cache is kept, and the error returned.
+    pub fn try_reload(&mut self) -> Result<(), AptError> {
         self.ptr_mutex.lock().expect("poisoned mutex").re_up()
     }
 
+    /// Start planning some changes, against a private copy of the dependency state.
+    ///
+    /// The cache is locked until the `Transaction` is dropped.
+    pub fn transaction(&mut self) -> Result<Transaction<'_>, AptError> {
+        let lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        Transaction::new(lock)
+    }
+
     /// Walk through all of the packages, in a random order.
     ///
     /// If there are multiple architectures, multiple architectures will be returned.
@@ -45,8 +82,8 @@ impl Cache {
         }
     }
 
-    /// Find a package by name. It's not clear whether this picks a random arch,
-    /// or the primary one.
+    /// Find a package by name, in the native architecture (`APT::Architecture`).
+    /// `groups()` can find the other architectures of a package.
     ///
     /// The returned iterator will either be at the end, or at a package with the name.
     pub fn find_by_name(&mut self, name: &str) -> CIterator<PkgIterator<'_>> {
@@ -58,6 +95,79 @@ impl Cache {
         }
     }
 
+    /// Walk through all of the package groups: everything with the same name, across
+    /// all of the architectures.
+    pub fn groups(&mut self) -> CIterator<GrpIterator<'_>> {
+        let lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        let ptr = unsafe { raw::pkg_cache_grp_iter(lock.ptr) };
+        CIterator {
+            first: true,
+            raw: GrpIterator { _cache: lock, ptr },
+        }
+    }
+
+    /// The package with this ID, if there is one, as a list of (at most) one.
+    ///
+    /// IDs are only meaningful for the cache they came from; they'll be different
+    /// after a `reload()` or `CacheBuilder::build()`.
+    pub fn package_by_id(&mut self, id: PackageId) -> CIterator<PkgIterator<'_>> {
+        let lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        unsafe {
+            let ptr = raw::pkg_cache_find_package_id(lock.ptr, id.0);
+            PkgIterator::new(lock, ptr)
+        }
+    }
+
+    /// The version with this ID, if there is one, as a list of (at most) one.
+    pub fn version_by_id(&mut self, id: VersionId) -> CIterator<VerIterator<'_>> {
+        let lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        let ptr = unsafe { raw::pkg_cache_find_version_id(lock.ptr, id.0) };
+        CIterator {
+            first: true,
+            raw: VerIterator {
+                _cache: Some(lock),
+                cache: PhantomData,
+                ptr,
+            },
+        }
+    }
+
+    /// Every version which has to be present for `roots` to be installed, and why it's
+    /// needed, following dependencies as set by `opts`. Unknown IDs are ignored.
+    pub fn dependency_closure(&mut self, roots: &[VersionId], opts: ClosureOpts) -> Closure {
+        Closure::new(self, roots, opts)
+    }
+
+    /// The shortest ways each package installed by hand needs `name` (or `name:arch`),
+    /// through `Depends`, `Pre-Depends` and `Recommends` of installed versions.
+    ///
+    /// Nearest first. Empty if it isn't installed, or nothing installed by hand needs it,
+    /// in which case `apt autoremove` would remove it.
+    pub fn why(&mut self, name: &str) -> Vec<why::Chain> {
+        why::why(self, name)
+    }
+
+    /// The `Conflicts` and `Breaks` between `name`'s candidate and installed packages,
+    /// in either direction. Empty if there's no such package, or it has no candidate.
+    pub fn why_not(&mut self, name: &str) -> Vec<why::Conflict> {
+        why::why_not(self, name)
+    }
+
+    /// This machine's id, from `/etc/machine-id` under the root, which apt uses to decide
+    /// which phased updates it takes, unless `APT::Machine-ID` is set. `None` if it has none,
+    /// in which case apt takes them all.
+    pub fn machine_id(&self) -> Option<String> {
+        let _lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        upgrade::machine_id()
+    }
+
+    /// Installed packages whose candidate is another version, as `apt list --upgradable`
+    /// shows them. Upgrades available from an archive matching any of `security` are marked
+    /// as security updates, e.g. with `label=Debian-Security`.
+    pub fn upgradable(&mut self, security: &[OriginPattern]) -> Vec<upgrade::Upgradable> {
+        upgrade::upgradable(self, security)
+    }
+
     /// Find a package by name and architecture.
     ///
     /// The returned iterator will either be at the end, or at a matching package.
@@ -71,6 +181,97 @@ impl Cache {
         }
     }
 
+    /// Find packages whose names match a shell-style glob, like `apt list 'libc6*'`.
+    ///
+    /// If the glob contains a `:`, it is matched against `name:arch` instead.
+    pub fn find_by_glob(&mut self, glob: &str) -> CIterator<PkgIterator<'_>> {
+        let lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        unsafe {
+            let glob = ffi::CString::new(glob).unwrap();
+            let ptr = raw::pkg_cache_find_glob(lock.ptr, glob.as_ptr());
+            PkgIterator::new(lock, ptr)
+        }
+    }
+
+    /// Find packages whose names match a (case insensitive, POSIX extended) regex.
+    pub fn find_by_regex(&mut self, regex: &str) -> Result<CIterator<PkgIterator<'_>>, AptError> {
+        let lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        unsafe {
+            let regex = ffi::CString::new(regex).unwrap();
+            let ptr = raw::pkg_cache_find_regex(lock.ptr, regex.as_ptr());
+            if ptr.is_null() {
+                return Err(AptError::take("invalid regex"));
+            }
+            Ok(PkgIterator::new(lock, ptr))
+        }
+    }
+
+    /// Search like `apt-cache search`: a (case insensitive, POSIX extended) regex is
+    /// matched against the names and descriptions of packages which have a candidate.
+    ///
+    /// The returned iterator only visits the matches, once per name: on a multi-arch system,
+    /// it's the native architecture's package, or whichever apt prefers.
+    pub fn search(
+        &mut self,
+        regex: &str,
+        opts: SearchOpts,
+    ) -> Result<CIterator<PkgIterator<'_>>, AptError> {
+        let lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        unsafe {
+            let regex = ffi::CString::new(regex).unwrap();
+            let ptr = raw::pkg_cache_search(lock.ptr, regex.as_ptr(), opts.names_only, opts.full);
+            if ptr.is_null() {
+                return Err(AptError::take("invalid regex"));
+            }
+            Ok(PkgIterator::new(lock, ptr))
+        }
+    }
+
+    /// Find packages matching an apt pattern, like `apt list '?and(?installed, ?section(libs))'`.
+    ///
+    /// See `apt-patterns(7)` for the language. The returned iterator only visits the matches.
+    /// Patterns need apt 1.9 or later; older versions always return `PatternError::Apt`.
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn select(&mut self, pattern: &str) -> Result<CIterator<PkgIterator<'_>>, PatternError> {
+        let lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        unsafe {
+            let pattern = ffi::CString::new(pattern).unwrap();
+            let ptr = raw::pkg_cache_select(lock.ptr, pattern.as_ptr());
+            if ptr.is_null() {
+                return Err(PatternError::from_apt(AptError::take("invalid pattern")));
+            }
+            Ok(PkgIterator::new(lock, ptr))
+        }
+    }
+
+    /// The architecture of this machine, as far as apt is concerned (`APT::Architecture`).
+    pub fn native_arch(&self) -> String {
+        let _lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        unsafe { raw::make_owned_string_free(raw::config_native_arch()) }
+            .expect("there's always a native architecture")
+    }
+
+    /// Which installed packages own the file at `path`, like `dpkg -S`, as dpkg would name
+    /// them, i.e. `name` or `name:arch`. Diversions are taken into account.
+    ///
+    /// This reads every package's file list, so isn't quick. Like dpkg, symlinks aren't
+    /// followed, so `/bin/sh` may have no owner, when `/usr/bin/sh` does.
+    pub fn package_owning<P: AsRef<Path>>(&self, path: P) -> io::Result<Vec<String>> {
+        let _lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        dpkg::package_owning(path.as_ref())
+    }
+
+    /// All of the architectures packages are considered for, native first.
+    /// This includes `dpkg`'s foreign architectures, and any added with `CacheBuilder`.
+    pub fn architectures(&self) -> Vec<String> {
+        let _lock = self.ptr_mutex.lock().expect("poisoned mutex");
+        unsafe { raw::make_owned_string_free(raw::config_architectures()) }
+            .expect("there's always an architecture list")
+            .split(',')
+            .map(|arch| arch.to_string())
+            .collect()
+    }
+
     /// Compare two versions, returning an `Ordering`, as used by most Rusty `sort()` methods.
     ///
     /// This uses the "versioning scheme" currently set, which, in theory, can change,
@@ -96,10 +297,116 @@ impl Cache {
     }
 }
 
+/// How `Cache::search()` matches, as with `apt-cache search`'s flags.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchOpts {
+    /// Only look at package names (`--names-only`).
+    pub names_only: bool,
+    /// Look at the whole of the candidate's record, every field, as well as the
+    /// (translated) description.
+    pub full: bool,
+}
+
+/// Settings to (re-)build the singleton with.
+///
+/// There's still only one cache, so `build()` changes what every other `Cache` sees,
+/// and the settings are kept for any later `reload()`.
+#[derive(Clone, Debug, Default)]
+pub struct CacheBuilder {
+    deb_files: Vec<PathBuf>,
+    architectures: Vec<String>,
+    root: Option<PathBuf>,
+}
+
+impl CacheBuilder {
+    pub fn new() -> CacheBuilder {
+        CacheBuilder::default()
+    }
+
+    /// Add a local `.deb` to the cache, as `apt install ./foo.deb` does.
+    ///
+    /// Its version will appear in `PkgView::versions()`, and can be installed by a `Transaction`.
+    pub fn with_deb_file<P: AsRef<Path>>(mut self, path: P) -> CacheBuilder {
+        self.deb_files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add an architecture to `APT::Architectures`, as if it had been added with
+    /// `dpkg --add-architecture`, e.g. to look at `arm64` packages on an `amd64` machine.
+    ///
+    /// Packages will only appear for it if `apt update` has fetched lists for it.
+    pub fn with_architecture(mut self, arch: &str) -> CacheBuilder {
+        self.architectures.push(arch.to_string());
+        self
+    }
+
+    /// Look at another system, like an unpacked image, instead of this one, as `RootDir`.
+    ///
+    /// Its sources, lists and dpkg database are all read from under `root`, so it must
+    /// at least have a `/var/lib/dpkg/status`. Paths elsewhere in this crate are still
+    /// relative to the system being looked at, i.e. `/usr/bin/foo`, not `root/usr/bin/foo`.
+    pub fn with_root<P: AsRef<Path>>(mut self, root: P) -> CacheBuilder {
+        self.root = Some(root.as_ref().to_path_buf());
+        self
+    }
+
+    /// Throw away the current cache, and build one with these settings.
+    ///
+    /// If this fails, the previous cache is still usable.
+    pub fn build(self) -> Result<Cache, AptError> {
+        let cache = Cache::get_singleton();
+
+        // absolute, so a later reload() from another directory still finds them
+        let deb_files = self
+            .deb_files
+            .iter()
+            .map(|path| {
+                let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+                raw::path_to_cstring(&path)
+            })
+            .collect();
+
+        let options = raw::CacheOptions {
+            deb_files,
+            architectures: self
+                .architectures
+                .iter()
+                .map(|arch| ffi::CString::new(arch.as_str()).unwrap())
+                .collect(),
+            root: self.root.map(|root| {
+                let root = root.canonicalize().unwrap_or(root);
+                raw::path_to_cstring(&root)
+            }),
+        };
+
+        cache
+            .ptr_mutex
+            .lock()
+            .expect("poisoned mutex")
+            .rebuild(options)?;
+
+        Ok(cache)
+    }
+}
+
+/// apt's number for a package, unique within a cache. IDs are dense, from zero, so
+/// they're suitable for indexing side tables.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PackageId(pub u32);
+
+/// apt's number for a version, unique within a cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VersionId(pub u32);
+
+/// apt's number for a dependency, unique within a cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DependencyId(pub u32);
+
 /// An "iterator"/pointer to a point in a package list.
 #[derive(Debug)]
 pub struct PkgIterator<'c> {
-    _cache: MutexGuard<'c, raw::CacheHolder>,
+    /// Not present if this is a list borrowed from something else, e.g. a group.
+    _cache: Option<MutexGuard<'c, raw::CacheHolder>>,
     ptr: raw::PPkgIterator,
 }
 
@@ -107,7 +414,17 @@ impl<'c> PkgIterator<'c> {
     fn new(cache: MutexGuard<'c, raw::CacheHolder>, ptr: raw::PCache) -> CIterator<Self> {
         CIterator {
             first: true,
-            raw: PkgIterator { _cache: cache, ptr },
+            raw: PkgIterator {
+                _cache: Some(cache),
+                ptr,
+            },
+        }
+    }
+
+    fn borrowed(ptr: raw::PPkgIterator) -> CIterator<Self> {
+        CIterator {
+            first: true,
+            raw: PkgIterator { _cache: None, ptr },
         }
     }
 }
@@ -146,6 +463,10 @@ impl<'c> RawIterator for PkgIterator<'c> {
 
 /// Actual accessors
 impl<'c> PkgView<'c> {
+    pub fn id(&self) -> PackageId {
+        PackageId(unsafe { raw::pkg_iter_id(self.ptr) })
+    }
+
     pub fn name(&self) -> String {
         unsafe {
             make_owned_ascii_string(raw::pkg_iter_name(self.ptr))
@@ -168,15 +489,182 @@ impl<'c> PkgView<'c> {
         unsafe { make_owned_ascii_string(raw::pkg_iter_candidate_version(self.ptr)) }
     }
 
+    /// Whether apt installed this to satisfy a dependency, rather than it being asked for,
+    /// as in `apt-mark showauto`. Packages which aren't installed can still be marked.
+    pub fn is_auto_installed(&self) -> bool {
+        unsafe { raw::pkg_iter_is_auto(self.ptr) }
+    }
+
     pub fn versions(&self) -> CIterator<VerIterator<'_>> {
         CIterator {
             first: true,
             raw: VerIterator {
+                _cache: None,
                 cache: PhantomData,
                 ptr: unsafe { raw::pkg_iter_ver_iter(self.ptr) },
             },
         }
     }
+
+    /// The files the installed version put on disk, like `dpkg -L`, in the order dpkg lists
+    /// them, including directories. Files which have been diverted are where they ended up.
+    ///
+    /// Packages which aren't installed have no files.
+    pub fn installed_files(&self) -> io::Result<Vec<PathBuf>> {
+        if self.current_version().is_none() {
+            return Ok(Vec::new());
+        }
+
+        dpkg::installed_files(&self.name(), &self.arch())
+    }
+
+    /// The installed `/usr/share/doc/<name>/copyright`, if it's machine-readable.
+    ///
+    /// `None` if the package isn't installed, or there's no such file, or it's free-form text.
+    pub fn copyright(&self) -> io::Result<Option<Copyright>> {
+        if self.current_version().is_none() {
+            return Ok(None);
+        }
+
+        copyright::read(&self.name())
+    }
+
+    /// Check the installed files against their hashes, like `debsums` or `dpkg --verify`:
+    /// files from the package's `md5sums`, then conffiles, against the status file.
+    ///
+    /// Packages which aren't installed have nothing to check.
+    pub fn verify(&self) -> io::Result<Vec<dpkg::Verified>> {
+        let record = match self.installed_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => return Ok(Vec::new()),
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        };
+
+        dpkg::verify(&self.name(), &self.arch(), &record)
+    }
+
+    /// The installed version's entry in the dpkg status file, if it's installed.
+    pub fn installed_record(&self) -> Result<Option<InstalledRecord>, Deb822Error> {
+        let current = match self.current_version() {
+            Some(current) => current,
+            None => return Ok(None),
+        };
+
+        let mut vers = self.versions();
+        while let Some(ver) = vers.next() {
+            if ver.version() != current {
+                continue;
+            }
+
+            let mut ver_files = ver.origin_iter();
+            while let Some(ver_file) = ver_files.next() {
+                let from_status = ver_file
+                    .file()
+                    .any(|file| file.index_type() == "Debian dpkg status file");
+                if !from_status {
+                    continue;
+                }
+
+                return match ver_file.record() {
+                    Some(record) => InstalledRecord::parse(&record).map(Some),
+                    None => Ok(None),
+                };
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The versions which declare they provide this package, through `Provides:`.
+    pub fn provided_by(&self) -> CIterator<PrvIterator<'_>> {
+        CIterator {
+            first: true,
+            raw: PrvIterator {
+                cache: PhantomData,
+                ptr: unsafe { raw::pkg_iter_prv_iter(self.ptr) },
+            },
+        }
+    }
+
+    /// Every dependency, from any version of any package, which names this package.
+    /// Dependencies on something this provides aren't included.
+    pub fn rev_dep_iter(&self) -> CIterator<DepIterator<'_>> {
+        CIterator {
+            first: true,
+            raw: DepIterator {
+                cache: PhantomData,
+                ptr: unsafe { raw::pkg_iter_rev_dep_iter(self.ptr) },
+            },
+        }
+    }
+}
+
+/// An "iterator"/pointer to a point in the group list.
+#[derive(Debug)]
+pub struct GrpIterator<'c> {
+    _cache: MutexGuard<'c, raw::CacheHolder>,
+    ptr: raw::PGrpIterator,
+}
+
+pub struct GroupView<'c> {
+    cache: PhantomData<&'c MutexGuard<'c, raw::CacheHolder>>,
+    ptr: raw::PGrpIterator,
+}
+
+impl<'c> RawIterator for GrpIterator<'c> {
+    type View = GroupView<'c>;
+
+    fn is_end(&self) -> bool {
+        unsafe { raw::grp_iter_end(self.ptr) }
+    }
+
+    fn next(&mut self) {
+        unsafe { raw::grp_iter_next(self.ptr) }
+    }
+
+    fn as_view(&self) -> Self::View {
+        assert!(!self.is_end());
+
+        GroupView {
+            ptr: self.ptr,
+            cache: PhantomData,
+        }
+    }
+
+    fn release(&mut self) {
+        unsafe { raw::grp_iter_release(self.ptr) }
+    }
+}
+
+/// Actual accessors
+impl<'c> GroupView<'c> {
+    pub fn name(&self) -> String {
+        unsafe {
+            make_owned_ascii_string(raw::grp_iter_name(self.ptr)).expect("groups always have names")
+        }
+    }
+
+    /// Every architecture of the package, including virtual ones.
+    pub fn packages(&self) -> CIterator<PkgIterator<'_>> {
+        PkgIterator::borrowed(unsafe { raw::grp_iter_pkg_iter(self.ptr) })
+    }
+
+    /// The package apt would pick for the bare name: the native architecture, then
+    /// `all`, then any other configured architecture, preferring non-virtual packages.
+    pub fn find_preferred_pkg(&self) -> Option<SinglePkgView<'_>> {
+        let view = SinglePkgView {
+            view: PkgView {
+                cache: self.cache,
+                ptr: unsafe { raw::grp_iter_find_preferred_pkg(self.ptr) },
+            },
+        };
+
+        if unsafe { raw::pkg_iter_end(view.ptr) } {
+            None
+        } else {
+            Some(view)
+        }
+    }
 }
 
 /// Represents a single PkgView without associated PkgIterator. Derefs to
@@ -201,8 +689,32 @@ impl<'c> Drop for SinglePkgView<'c> {
     }
 }
 
+/// Represents a single VerView without associated VerIterator. Derefs to
+/// regular VerView and releases the internal iterator on drop.
+pub struct SingleVerView<'c> {
+    view: VerView<'c>,
+}
+
+impl<'c> std::ops::Deref for SingleVerView<'c> {
+    type Target = VerView<'c>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.view
+    }
+}
+
+impl<'c> Drop for SingleVerView<'c> {
+    fn drop(&mut self) {
+        unsafe {
+            raw::ver_iter_release(self.view.ptr);
+        }
+    }
+}
+
 /// An "iterator"/pointer to a point in a version list.
 pub struct VerIterator<'c> {
+    /// Only present if this came straight from the cache, e.g. `Cache::version_by_id()`.
+    _cache: Option<MutexGuard<'c, raw::CacheHolder>>,
     cache: PhantomData<&'c MutexGuard<'c, raw::CacheHolder>>,
     ptr: raw::PVerIterator,
 }
@@ -239,6 +751,10 @@ impl<'c> RawIterator for VerIterator<'c> {
 
 /// Actual accessors
 impl<'c> VerView<'c> {
+    pub fn id(&self) -> VersionId {
+        VersionId(unsafe { raw::ver_iter_id(self.ptr) })
+    }
+
     pub fn version(&self) -> String {
         unsafe {
             make_owned_ascii_string(raw::ver_iter_version(self.ptr))
@@ -261,6 +777,95 @@ impl<'c> VerView<'c> {
         unsafe { make_owned_ascii_string(raw::ver_iter_priority_type(self.ptr)) }
     }
 
+    pub fn multi_arch(&self) -> MultiArch {
+        MultiArch::from_flags(unsafe { raw::ver_iter_multi_arch(self.ptr) })
+    }
+
+    /// The package this is a version of.
+    pub fn package(&self) -> SinglePkgView<'_> {
+        let ptr = unsafe { raw::ver_iter_pkg(self.ptr) };
+        SinglePkgView {
+            view: PkgView {
+                cache: self.cache,
+                ptr,
+            },
+        }
+    }
+
+    /// The size of the `.deb`, in bytes, or 0 if it's not known, e.g. it's only in the status file.
+    pub fn size(&self) -> u64 {
+        unsafe { raw::ver_iter_size(self.ptr) }
+    }
+
+    /// The `Installed-Size`, in KiB, as estimated by the package.
+    pub fn installed_size(&self) -> u64 {
+        unsafe { raw::ver_iter_installed_size(self.ptr) }
+    }
+
+    /// The `.deb`'s path in the archive, e.g. `pool/main/a/apt/apt_2.6.1_amd64.deb`,
+    /// from the first package list it's in.
+    pub fn file_name(&self) -> Option<String> {
+        unsafe { raw::make_owned_string_free(raw::ver_iter_file_name(self.ptr)) }
+    }
+
+    /// The `.deb`'s hashes, from the first package list it's in.
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn hashes(&self) -> Vec<simple::Hash> {
+        unsafe { raw::make_owned_string_free(raw::ver_iter_hashes(self.ptr)) }
+            .expect("hashes are always returned")
+            .lines()
+            .filter_map(|line| {
+                let (kind, value) = line.split_once(':')?;
+                Some(simple::Hash {
+                    kind: kind.to_string(),
+                    value: value.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Where `apt-get download` would fetch the `.deb` from, i.e. `file_name()` under the
+    /// archive's base URI from `sources.list`.
+    pub fn download_uri(&self) -> Option<String> {
+        unsafe { raw::make_owned_string_free(raw::ver_iter_download_uri(self.ptr)) }
+    }
+
+    /// Where apt would get this version's changelog from, for `changelog::fetch()`, as
+    /// `apt changelog` does. This is the local copy, under `/usr/share/doc`, for the installed
+    /// version, if it's there, otherwise it's worked out from the `Changelogs` field of
+    /// the archive's `Release` file, or `Acquire::Changelogs::URI` settings.
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn changelog_uri(&self) -> Option<String> {
+        unsafe { raw::make_owned_string_free(raw::ver_iter_changelog_uri(self.ptr)) }
+    }
+
+    /// The description in a language, e.g. `de` or `pt_BR`, from the `Translation-*` files.
+    /// The untranslated description, from the `Packages` file, has language `""`,
+    /// unless the archive ships it as `Translation-en`.
+    ///
+    /// `None` picks the one apt would show, according to `Acquire::Languages`.
+    pub fn translated_description(&self, lang: Option<&str>) -> Option<simple::Description> {
+        let lang = lang.map(|lang| ffi::CString::new(lang).unwrap());
+        let lang_ptr = lang.as_ref().map_or(std::ptr::null(), |lang| lang.as_ptr());
+
+        unsafe {
+            let description = raw::ver_iter_translated_description(self.ptr, lang_ptr);
+            if description.is_null() {
+                return None;
+            }
+
+            let owned = |ptr| raw::make_owned_string_free(ptr).expect("descriptions are complete");
+            let result = simple::Description {
+                language: owned(raw::description_language(description)),
+                md5: owned(raw::description_md5(description)),
+                short_desc: owned(raw::description_short_desc(description)),
+                long_desc: owned(raw::description_long_desc(description)),
+            };
+            raw::description_release(description);
+            Some(result)
+        }
+    }
+
     #[cfg(not(feature = "ye-olde-apt"))]
     pub fn source_package(&self) -> String {
         unsafe {
@@ -282,6 +887,28 @@ impl<'c> VerView<'c> {
         unsafe { raw::ver_iter_priority(self.ptr) }
     }
 
+    /// The `Phased-Update-Percentage` of a version which is being rolled out gradually,
+    /// or `None` if it's available to everyone.
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn phased_update_percentage(&self) -> Option<u8> {
+        match unsafe { raw::ver_iter_phased_update_percentage(self.ptr) } {
+            100 => None,
+            percentage => Some(percentage),
+        }
+    }
+
+    /// Whether apt would upgrade to this version yet, on the machine `phasing` picks, or
+    /// keep it back as a phased update. Phasing only applies to the candidate of an installed
+    /// package, so anything else always is, as is everything with an apt too old to phase.
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn is_phased_in(&self, phasing: &Phasing) -> bool {
+        let (machine_id, include_all) = phasing.to_raw();
+        let machine_id = machine_id
+            .as_ref()
+            .map_or(std::ptr::null(), |id| id.as_ptr());
+        unsafe { raw::ver_iter_phased_in(self.ptr, machine_id, include_all) }
+    }
+
     pub fn origin_iter(&self) -> CIterator<VerFileIterator<'_>> {
         CIterator {
             first: true,
@@ -301,6 +928,45 @@ impl<'c> VerView<'c> {
             },
         }
     }
+
+    /// The (virtual, or real) packages this version says it provides.
+    pub fn provides(&self) -> CIterator<PrvIterator<'_>> {
+        CIterator {
+            first: true,
+            raw: PrvIterator {
+                cache: PhantomData,
+                ptr: unsafe { raw::ver_iter_prv_iter(self.ptr) },
+            },
+        }
+    }
+}
+
+/// A version's `Multi-Arch` field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MultiArch {
+    /// Not present; the package can only satisfy dependencies from its own architecture.
+    No,
+    /// Co-installable with the same package from other architectures.
+    Same,
+    /// Can satisfy dependencies from any architecture.
+    Foreign,
+    /// Can satisfy dependencies from any architecture which ask for `:any`.
+    Allowed,
+}
+
+impl MultiArch {
+    fn from_flags(flags: u8) -> MultiArch {
+        // pkgCache::Version::VerMultiArch; the `All` bit is for Architecture: all
+        if flags & 4 != 0 {
+            MultiArch::Same
+        } else if flags & 2 != 0 {
+            MultiArch::Foreign
+        } else if flags & 8 != 0 {
+            MultiArch::Allowed
+        } else {
+            MultiArch::No
+        }
+    }
 }
 
 /// An "iterator"/pointer to a point in a dependency list.
@@ -341,6 +1007,21 @@ impl<'c> RawIterator for DepIterator<'c> {
 
 /// Actual accessors
 impl<'c> DepView<'c> {
+    pub fn id(&self) -> DependencyId {
+        DependencyId(unsafe { raw::dep_iter_id(self.ptr) })
+    }
+
+    /// The version which has this dependency.
+    pub fn parent_ver(&self) -> SingleVerView<'_> {
+        let ptr = unsafe { raw::dep_iter_parent_ver(self.ptr) };
+        SingleVerView {
+            view: VerView {
+                cache: self.cache,
+                ptr,
+            },
+        }
+    }
+
     pub fn target_pkg(&self) -> SinglePkgView<'_> {
         let ptr = unsafe { raw::dep_iter_target_pkg(self.ptr) };
         SinglePkgView {
@@ -351,6 +1032,8 @@ impl<'c> DepView<'c> {
         }
     }
 
+    /// The version in the constraint. Only versioned dependencies have one,
+    /// i.e. those with a non-empty `comp_type()`; this panics for the others.
     pub fn target_ver(&self) -> String {
         unsafe {
             make_owned_ascii_string(raw::dep_iter_target_ver(self.ptr))
@@ -371,6 +1054,99 @@ impl<'c> DepView<'c> {
                 .expect("dependency always has dep type")
         }
     }
+
+    /// Whether the next dependency is an alternative to this one, i.e. they're separated by `|`.
+    pub fn is_or(&self) -> bool {
+        unsafe { raw::dep_iter_is_or(self.ptr) }
+    }
+
+    /// Every version which would satisfy this dependency on its own: the versions of
+    /// `target_pkg()` which match the constraint, then any versions which provide it.
+    /// Whether they're installed, or could be, isn't considered.
+    pub fn all_targets(&self) -> CIterator<VerIterator<'_>> {
+        CIterator {
+            first: true,
+            raw: VerIterator {
+                _cache: None,
+                cache: PhantomData,
+                ptr: unsafe { raw::dep_iter_all_targets(self.ptr) },
+            },
+        }
+    }
+}
+
+/// An "iterator"/pointer to a point in a provides list.
+pub struct PrvIterator<'c> {
+    cache: PhantomData<&'c MutexGuard<'c, raw::CacheHolder>>,
+    ptr: raw::PPrvIterator,
+}
+
+pub struct PrvView<'c> {
+    cache: PhantomData<&'c MutexGuard<'c, raw::CacheHolder>>,
+    ptr: raw::PPrvIterator,
+}
+
+impl<'c> RawIterator for PrvIterator<'c> {
+    type View = PrvView<'c>;
+
+    fn is_end(&self) -> bool {
+        unsafe { raw::prv_iter_end(self.ptr) }
+    }
+
+    fn next(&mut self) {
+        unsafe { raw::prv_iter_next(self.ptr) }
+    }
+
+    fn as_view(&self) -> Self::View {
+        assert!(!self.is_end());
+
+        PrvView {
+            ptr: self.ptr,
+            cache: self.cache,
+        }
+    }
+
+    fn release(&mut self) {
+        unsafe { raw::prv_iter_release(self.ptr) }
+    }
+}
+
+/// Actual accessors
+impl<'c> PrvView<'c> {
+    /// The package being provided.
+    pub fn target_pkg(&self) -> SinglePkgView<'_> {
+        let ptr = unsafe { raw::prv_iter_target_pkg(self.ptr) };
+        SinglePkgView {
+            view: PkgView {
+                cache: self.cache,
+                ptr,
+            },
+        }
+    }
+
+    /// The version given in `Provides: foo (= 1.2)`, if any.
+    pub fn provide_version(&self) -> Option<String> {
+        unsafe { make_owned_ascii_string(raw::prv_iter_provide_version(self.ptr)) }
+    }
+
+    /// The package doing the providing.
+    pub fn owner_pkg(&self) -> SinglePkgView<'_> {
+        let ptr = unsafe { raw::prv_iter_owner_pkg(self.ptr) };
+        SinglePkgView {
+            view: PkgView {
+                cache: self.cache,
+                ptr,
+            },
+        }
+    }
+
+    /// The version of `owner_pkg()` doing the providing.
+    pub fn owner_version(&self) -> String {
+        unsafe {
+            make_owned_ascii_string(raw::prv_iter_owner_version(self.ptr))
+                .expect("versions always have a version")
+        }
+    }
 }
 
 /// An "iterator"/pointer to a point in a version's file list(?).
@@ -415,6 +1191,12 @@ impl<'c> RawIterator for VerFileIterator<'c> {
     }
 }
 
+impl<'c> Drop for VerFileView<'c> {
+    fn drop(&mut self) {
+        unsafe { raw::ver_file_parser_release(self.parser) }
+    }
+}
+
 impl<'c> VerFileView<'c> {
     pub fn file(&self) -> CIterator<PkgFileIterator<'_>> {
         CIterator {
@@ -427,19 +1209,25 @@ impl<'c> VerFileView<'c> {
     }
 
     pub fn short_desc(&self) -> Option<String> {
-        unsafe { make_owned_ascii_string(raw::ver_file_parser_short_desc(self.parser)) }
+        unsafe { raw::make_owned_string_free(raw::ver_file_parser_short_desc(self.parser)) }
     }
 
     pub fn long_desc(&self) -> Option<String> {
-        unsafe { make_owned_ascii_string(raw::ver_file_parser_long_desc(self.parser)) }
+        unsafe { raw::make_owned_string_free(raw::ver_file_parser_long_desc(self.parser)) }
     }
 
     pub fn maintainer(&self) -> Option<String> {
-        unsafe { make_owned_ascii_string(raw::ver_file_parser_maintainer(self.parser)) }
+        unsafe { raw::make_owned_string_free(raw::ver_file_parser_maintainer(self.parser)) }
     }
 
     pub fn homepage(&self) -> Option<String> {
-        unsafe { make_owned_ascii_string(raw::ver_file_parser_homepage(self.parser)) }
+        unsafe { raw::make_owned_string_free(raw::ver_file_parser_homepage(self.parser)) }
+    }
+
+    /// The whole stanza for this version, as it appears in the file, e.g. for fields
+    /// which don't have their own accessor.
+    pub fn record(&self) -> Option<String> {
+        unsafe { raw::make_owned_string_free(raw::ver_file_parser_record(self.parser)) }
     }
 }
 
@@ -527,7 +1315,7 @@ impl<'c> PkgFileView<'c> {
 }
 
 #[inline]
-unsafe fn make_owned_ascii_string(ptr: *const libc::c_char) -> Option<String> {
+pub(crate) unsafe fn make_owned_ascii_string(ptr: *const libc::c_char) -> Option<String> {
     if ptr.is_null() {
         return None;
     }
@@ -0,0 +1,587 @@
+//! Software bills of materials for what's installed, as SPDX 2.3 or CycloneDX 1.5 JSON.
+//!
+//! The documents here are plain `serde` types; write them out with `serde_json`:
+//!
+//! ```rust,no_run
+//! # let mut cache = apt_pkg_native::Cache::get_singleton();
+//! let sbom = apt_pkg_native::sbom::Sbom::installed(&mut cache);
+//! println!("{}", serde_json::to_string_pretty(&sbom.spdx("my-image")).unwrap());
+//! ```
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::Cache;
+use crate::sane::PackageId;
+use crate::sane::PkgView;
+use crate::sane::VerView;
+use crate::simple::Hash;
+
+/// An installed package.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Component {
+    pub name: String,
+    pub arch: String,
+    pub version: String,
+    /// Not known with `ye-olde-apt`.
+    pub source_package: Option<String>,
+    pub source_version: Option<String>,
+    pub maintainer: Option<String>,
+    pub homepage: Option<String>,
+    /// The archive's `Origin` and `Codename`, e.g. `Debian` and `bookworm`, if the installed
+    /// version is still available from one.
+    pub origin: Option<String>,
+    pub codename: Option<String>,
+    /// Of the `.deb`, from the package lists. Empty if it's not in any.
+    pub hashes: Vec<Hash>,
+    /// e.g. `pkg:deb/debian/foo@1.2?arch=amd64&distro=bookworm`. Packages from an unknown
+    /// origin are assumed to be from Debian.
+    pub purl: String,
+    /// Indexes, into `Sbom::components()`, of the installed packages this one's
+    /// `Depends` and `Pre-Depends` are satisfied by.
+    pub depends_on: Vec<usize>,
+}
+
+/// Everything installed, ready to be written out in either format.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sbom {
+    components: Vec<Component>,
+}
+
+impl Sbom {
+    pub fn installed(cache: &mut Cache) -> Sbom {
+        let mut components = Vec::new();
+        let mut indexes = HashMap::new();
+        let mut depends_on = Vec::new();
+
+        let mut pkgs = cache.iter();
+        while let Some(pkg) = pkgs.next() {
+            let current = match pkg.current_version() {
+                Some(current) => current,
+                None => continue,
+            };
+
+            let mut vers = pkg.versions();
+            while let Some(ver) = vers.next() {
+                if ver.version() != current {
+                    continue;
+                }
+
+                indexes.insert(pkg.id(), components.len());
+                components.push(component(&pkg, &ver));
+                depends_on.push(installed_deps(&pkg, &ver));
+                break;
+            }
+        }
+        drop(pkgs);
+
+        for (component, deps) in components.iter_mut().zip(depends_on) {
+            component.depends_on = deps
+                .iter()
+                .filter_map(|id| indexes.get(id))
+                .copied()
+                .collect();
+        }
+
+        Sbom { components }
+    }
+
+    /// In cache order.
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
+
+    /// An SPDX 2.3 document, which `DESCRIBES` every package.
+    pub fn spdx(&self, name: &str) -> spdx::Document {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let spdx_id = |idx: usize| format!("SPDXRef-Package-{idx}");
+
+        let packages = self
+            .components
+            .iter()
+            .enumerate()
+            .map(|(idx, component)| spdx::Package {
+                spdx_id: spdx_id(idx),
+                name: component.name.clone(),
+                version_info: component.version.clone(),
+                supplier: component
+                    .maintainer
+                    .as_ref()
+                    .map(|maintainer| format!("Person: {}", spdx_person(maintainer)))
+                    .unwrap_or_else(|| "NOASSERTION".to_string()),
+                download_location: "NOASSERTION".to_string(),
+                files_analyzed: false,
+                homepage: component.homepage.clone(),
+                source_info: component.source_package.as_ref().map(|source| {
+                    let version = component.source_version.as_deref().unwrap_or("");
+                    format!("built package from: {source} {version}")
+                }),
+                checksums: component
+                    .hashes
+                    .iter()
+                    .filter_map(|hash| {
+                        Some(spdx::Checksum {
+                            algorithm: hash_name(&hash.kind)?.0.to_string(),
+                            checksum_value: hash.value.clone(),
+                        })
+                    })
+                    .collect(),
+                external_refs: vec![spdx::ExternalRef {
+                    reference_category: "PACKAGE-MANAGER".to_string(),
+                    reference_type: "purl".to_string(),
+                    reference_locator: component.purl.clone(),
+                }],
+            })
+            .collect();
+
+        let mut relationships = Vec::new();
+        for (idx, component) in self.components.iter().enumerate() {
+            relationships.push(spdx::Relationship {
+                spdx_element_id: "SPDXRef-DOCUMENT".to_string(),
+                relationship_type: "DESCRIBES".to_string(),
+                related_spdx_element: spdx_id(idx),
+            });
+            for &dep in &component.depends_on {
+                relationships.push(spdx::Relationship {
+                    spdx_element_id: spdx_id(idx),
+                    relationship_type: "DEPENDS_ON".to_string(),
+                    related_spdx_element: spdx_id(dep),
+                });
+            }
+        }
+
+        spdx::Document {
+            spdx_version: "SPDX-2.3".to_string(),
+            data_license: "CC0-1.0".to_string(),
+            spdx_id: "SPDXRef-DOCUMENT".to_string(),
+            name: name.to_string(),
+            document_namespace: format!(
+                "https://spdx.org/spdxdocs/{}-{:x}{:08x}",
+                percent_encode(name),
+                now.as_secs(),
+                now.subsec_nanos()
+            ),
+            creation_info: spdx::CreationInfo {
+                created: rfc3339(now.as_secs()),
+                creators: vec![format!("Tool: {}-{}", TOOL, env!("CARGO_PKG_VERSION"))],
+            },
+            packages,
+            relationships,
+        }
+    }
+
+    /// A CycloneDX 1.5 document. Components are identified by their purl.
+    pub fn cyclonedx(&self) -> cyclonedx::Document {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let components = self
+            .components
+            .iter()
+            .map(|component| {
+                let mut properties = vec![cyclonedx::Property {
+                    name: "deb:arch".to_string(),
+                    value: component.arch.clone(),
+                }];
+                if let Some(ref source) = component.source_package {
+                    properties.push(cyclonedx::Property {
+                        name: "deb:source".to_string(),
+                        value: source.clone(),
+                    });
+                }
+                if let Some(ref version) = component.source_version {
+                    properties.push(cyclonedx::Property {
+                        name: "deb:source_version".to_string(),
+                        value: version.clone(),
+                    });
+                }
+
+                cyclonedx::Component {
+                    kind: "library".to_string(),
+                    bom_ref: component.purl.clone(),
+                    name: component.name.clone(),
+                    version: component.version.clone(),
+                    publisher: component.maintainer.clone(),
+                    purl: component.purl.clone(),
+                    hashes: component
+                        .hashes
+                        .iter()
+                        .filter_map(|hash| {
+                            Some(cyclonedx::Hash {
+                                alg: hash_name(&hash.kind)?.1.to_string(),
+                                content: hash.value.clone(),
+                            })
+                        })
+                        .collect(),
+                    external_references: component
+                        .homepage
+                        .iter()
+                        .map(|homepage| cyclonedx::ExternalReference {
+                            kind: "website".to_string(),
+                            url: homepage.clone(),
+                        })
+                        .collect(),
+                    properties,
+                }
+            })
+            .collect();
+
+        let dependencies = self
+            .components
+            .iter()
+            .map(|component| cyclonedx::Dependency {
+                bom_ref: component.purl.clone(),
+                depends_on: component
+                    .depends_on
+                    .iter()
+                    .map(|&dep| self.components[dep].purl.clone())
+                    .collect(),
+            })
+            .collect();
+
+        cyclonedx::Document {
+            bom_format: "CycloneDX".to_string(),
+            spec_version: "1.5".to_string(),
+            version: 1,
+            metadata: cyclonedx::Metadata {
+                timestamp: rfc3339(now.as_secs()),
+                tools: cyclonedx::Tools {
+                    components: vec![cyclonedx::Tool {
+                        kind: "application".to_string(),
+                        name: TOOL.to_string(),
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                    }],
+                },
+            },
+            components,
+            dependencies,
+        }
+    }
+}
+
+const TOOL: &str = env!("CARGO_PKG_NAME");
+
+fn component(pkg: &PkgView, ver: &VerView) -> Component {
+    let (mut maintainer, mut homepage) = (None, None);
+    let (mut origin, mut codename) = (None, None);
+
+    let mut ver_files = ver.origin_iter();
+    while let Some(ver_file) = ver_files.next() {
+        if maintainer.is_none() {
+            maintainer = ver_file.maintainer();
+            homepage = ver_file.homepage();
+        }
+
+        let mut pkg_files = ver_file.file();
+        while let Some(pkg_file) = pkg_files.next() {
+            if origin.is_none() && pkg_file.index_type() != "Debian dpkg status file" {
+                origin = pkg_file.origin();
+                codename = pkg_file.codename();
+            }
+        }
+    }
+
+    #[cfg(not(feature = "ye-olde-apt"))]
+    let (source_package, source_version, hashes) = (
+        Some(ver.source_package()),
+        Some(ver.source_version()),
+        ver.hashes(),
+    );
+    #[cfg(feature = "ye-olde-apt")]
+    let (source_package, source_version, hashes) = (None, None, Vec::new());
+
+    let name = pkg.name();
+    let version = ver.version();
+    let arch = ver.arch();
+    let purl = purl(
+        &name,
+        &version,
+        &arch,
+        origin.as_deref(),
+        codename.as_deref(),
+    );
+
+    Component {
+        name,
+        arch,
+        version,
+        source_package,
+        source_version,
+        maintainer,
+        homepage,
+        origin,
+        codename,
+        hashes,
+        purl,
+        depends_on: Vec::new(),
+    }
+}
+
+/// The installed packages which satisfy `ver`'s hard dependencies; for an or-group,
+/// the first alternative which is installed.
+fn installed_deps(pkg: &PkgView, ver: &VerView) -> Vec<PackageId> {
+    let own = pkg.id();
+    let mut found = Vec::new();
+    let mut satisfied = None;
+
+    let mut deps = ver.dep_iter();
+    while let Some(dep) = deps.next() {
+        if satisfied.is_none() && matches!(dep.dep_type().as_str(), "Depends" | "PreDepends") {
+            satisfied = dep
+                .all_targets()
+                .filter_map(|target| {
+                    let target_pkg = target.package();
+                    let installed = target_pkg.current_version() == Some(target.version());
+                    installed.then(|| target_pkg.id())
+                })
+                .next();
+        }
+
+        if !dep.is_or()
+            && let Some(id) = satisfied.take()
+            && id != own
+            && !found.contains(&id)
+        {
+            found.push(id);
+        }
+    }
+
+    found
+}
+
+/// `pkg:deb/<origin>/<name>@<version>?arch=<arch>&distro=<codename>`, per the purl spec.
+fn purl(
+    name: &str,
+    version: &str,
+    arch: &str,
+    origin: Option<&str>,
+    codename: Option<&str>,
+) -> String {
+    let namespace = origin.unwrap_or("debian").to_lowercase();
+    let mut purl = format!(
+        "pkg:deb/{}/{}@{}?arch={}",
+        percent_encode(&namespace),
+        percent_encode(name),
+        percent_encode(version),
+        percent_encode(arch)
+    );
+    if let Some(codename) = codename {
+        purl.push_str("&distro=");
+        purl.push_str(&percent_encode(codename));
+    }
+    purl
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// SPDX's and CycloneDX's names for apt's hash types.
+fn hash_name(kind: &str) -> Option<(&'static str, &'static str)> {
+    Some(match kind {
+        "MD5Sum" => ("MD5", "MD5"),
+        "SHA1" => ("SHA1", "SHA-1"),
+        "SHA256" => ("SHA256", "SHA-256"),
+        "SHA512" => ("SHA512", "SHA-512"),
+        _ => return None,
+    })
+}
+
+/// `Name <email>`, as in control files, is `Name (email)` in SPDX.
+fn spdx_person(maintainer: &str) -> String {
+    match maintainer.split_once('<') {
+        Some((name, email)) => format!("{} ({})", name.trim(), email.trim_end_matches('>')),
+        None => maintainer.to_string(),
+    }
+}
+
+/// e.g. `2024-02-29T12:34:56Z`, without pulling in a date library.
+fn rfc3339(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// The parts of SPDX 2.3 which are filled in.
+pub mod spdx {
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Document {
+        pub spdx_version: String,
+        pub data_license: String,
+        #[serde(rename = "SPDXID")]
+        pub spdx_id: String,
+        pub name: String,
+        pub document_namespace: String,
+        pub creation_info: CreationInfo,
+        pub packages: Vec<Package>,
+        pub relationships: Vec<Relationship>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct CreationInfo {
+        pub created: String,
+        pub creators: Vec<String>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Package {
+        #[serde(rename = "SPDXID")]
+        pub spdx_id: String,
+        pub name: String,
+        pub version_info: String,
+        pub supplier: String,
+        pub download_location: String,
+        pub files_analyzed: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub homepage: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub source_info: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub checksums: Vec<Checksum>,
+        pub external_refs: Vec<ExternalRef>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Checksum {
+        pub algorithm: String,
+        pub checksum_value: String,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct ExternalRef {
+        pub reference_category: String,
+        pub reference_type: String,
+        pub reference_locator: String,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Relationship {
+        pub spdx_element_id: String,
+        pub relationship_type: String,
+        pub related_spdx_element: String,
+    }
+}
+
+/// The parts of CycloneDX 1.5 which are filled in.
+pub mod cyclonedx {
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Document {
+        pub bom_format: String,
+        pub spec_version: String,
+        pub version: u32,
+        pub metadata: Metadata,
+        pub components: Vec<Component>,
+        pub dependencies: Vec<Dependency>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Metadata {
+        pub timestamp: String,
+        pub tools: Tools,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Tools {
+        pub components: Vec<Tool>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Tool {
+        #[serde(rename = "type")]
+        pub kind: String,
+        pub name: String,
+        pub version: String,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Component {
+        #[serde(rename = "type")]
+        pub kind: String,
+        #[serde(rename = "bom-ref")]
+        pub bom_ref: String,
+        pub name: String,
+        pub version: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub publisher: Option<String>,
+        pub purl: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub hashes: Vec<Hash>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub external_references: Vec<ExternalReference>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub properties: Vec<Property>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Hash {
+        pub alg: String,
+        pub content: String,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct ExternalReference {
+        #[serde(rename = "type")]
+        pub kind: String,
+        pub url: String,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Property {
+        pub name: String,
+        pub value: String,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Dependency {
+        #[serde(rename = "ref")]
+        pub bom_ref: String,
+        pub depends_on: Vec<String>,
+    }
+}
@@ -0,0 +1,276 @@
+//! Checking what's installed against the Debian security tracker's JSON export,
+//! <https://security-tracker.debian.org/tracker/data/json>, e.g. to scan machines offline.
+//!
+//! The export is read with `serde`; load a downloaded copy with `serde_json`:
+//!
+//! ```rust,no_run
+//! use apt_pkg_native::security::{ScanOpts, Tracker};
+//!
+//! # let mut cache = apt_pkg_native::Cache::get_singleton();
+//! let file = std::fs::File::open("tracker.json").unwrap();
+//! let tracker: Tracker = serde_json::from_reader(std::io::BufReader::new(file)).unwrap();
+//! for finding in tracker.scan(&mut cache, &ScanOpts::new("bookworm")) {
+//!     println!("{finding}");
+//! }
+//! ```
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::Cache;
+use crate::sane::VerView;
+use crate::simple::Origin;
+use crate::simple::OriginPattern;
+
+/// The whole export: for each source package, its issues, by name (a CVE, or a
+/// `TEMP-` id for issues which don't have one yet).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Tracker {
+    pub packages: HashMap<String, HashMap<String, Issue>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Issue {
+    #[serde(default)]
+    pub description: Option<String>,
+    /// `local` or `remote`.
+    #[serde(default)]
+    pub scope: Option<String>,
+    #[serde(default)]
+    pub debianbug: Option<u64>,
+    /// By codename, e.g. `bookworm`.
+    #[serde(default)]
+    pub releases: HashMap<String, Release>,
+}
+
+/// The state of an issue in one release.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Release {
+    /// `resolved`, `open` or `undetermined`.
+    pub status: String,
+    /// The version fixing it, if `resolved`. `0` means the release was never affected.
+    #[serde(default)]
+    pub fixed_version: Option<String>,
+    /// e.g. `low`, `medium`, `high`, `unimportant`, or `not yet assigned`.
+    #[serde(default)]
+    pub urgency: Option<String>,
+    /// The version of the source in each suite, e.g. `bookworm-security`.
+    #[serde(default)]
+    pub repositories: HashMap<String, String>,
+}
+
+/// What to check against.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScanOpts {
+    /// The codename the machine is running, as the tracker names releases.
+    pub release: String,
+    /// Where fixes may be installed from. If empty, any archive will do.
+    pub fix_origins: Vec<OriginPattern>,
+}
+
+impl ScanOpts {
+    pub fn new(release: &str) -> ScanOpts {
+        ScanOpts {
+            release: release.to_string(),
+            fix_origins: Vec::new(),
+        }
+    }
+}
+
+/// An issue affecting an installed source package.
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub source: String,
+    /// As installed.
+    pub source_version: String,
+    /// The installed binary packages built from it, as `name:arch`.
+    pub binaries: Vec<String>,
+    pub issue: String,
+    pub description: Option<String>,
+    pub urgency: Option<String>,
+    /// `None` while the issue is still open in the release.
+    pub fixed_version: Option<String>,
+    /// The best version available from the `fix_origins` which contains the fix, if any.
+    pub fix: Option<AvailableFix>,
+}
+
+/// A binary package version which would fix a `Finding`.
+#[derive(Clone, Debug)]
+pub struct AvailableFix {
+    pub package: String,
+    pub version: String,
+    pub source_version: String,
+    pub origin: Origin,
+}
+
+/// An installed binary package, with everything else its package could be upgraded to.
+struct Installed {
+    name: String,
+    source: String,
+    source_version: String,
+    available: Vec<AvailableFix>,
+}
+
+impl Tracker {
+    /// Every issue affecting an installed source package in `opts.release`, ordered by
+    /// source package, then issue. Issues the tracker has marked `unimportant` are included;
+    /// check `urgency` to leave them out.
+    pub fn scan(&self, cache: &mut Cache, opts: &ScanOpts) -> Vec<Finding> {
+        // grouped by source and version, as a partial upgrade can leave several installed
+        let mut installed: BTreeMap<(String, String), Vec<Installed>> = BTreeMap::new();
+        for binary in installed_binaries(cache, opts) {
+            if self.packages.contains_key(&binary.source) {
+                installed
+                    .entry((binary.source.clone(), binary.source_version.clone()))
+                    .or_default()
+                    .push(binary);
+            }
+        }
+
+        let mut findings = Vec::new();
+        for ((source, source_version), binaries) in installed {
+            let mut issues: Vec<_> = self.packages[&source].iter().collect();
+            issues.sort_by_key(|&(name, _)| name);
+
+            for (name, issue) in issues {
+                let release = match issue.releases.get(&opts.release) {
+                    Some(release) => release,
+                    None => continue,
+                };
+
+                let fixed_version = match release.status.as_str() {
+                    "resolved" => match release.fixed_version {
+                        Some(ref fixed) => Some(fixed.clone()),
+                        None => continue,
+                    },
+                    _ => None,
+                };
+
+                if let Some(ref fixed) = fixed_version
+                    && cache.compare_versions(&source_version, fixed) != cmp::Ordering::Less
+                {
+                    continue;
+                }
+
+                let fix = fixed_version
+                    .as_ref()
+                    .and_then(|fixed| best_fix(cache, &binaries, fixed));
+
+                findings.push(Finding {
+                    source: source.clone(),
+                    source_version: source_version.clone(),
+                    binaries: binaries.iter().map(|binary| binary.name.clone()).collect(),
+                    issue: name.clone(),
+                    description: issue.description.clone(),
+                    urgency: release.urgency.clone(),
+                    fixed_version,
+                    fix,
+                });
+            }
+        }
+
+        findings
+    }
+}
+
+fn installed_binaries(cache: &mut Cache, opts: &ScanOpts) -> Vec<Installed> {
+    let mut installed = Vec::new();
+
+    let mut pkgs = cache.iter();
+    while let Some(pkg) = pkgs.next() {
+        let current = match pkg.current_version() {
+            Some(current) => current,
+            None => continue,
+        };
+
+        let mut binary = None;
+        let mut available = Vec::new();
+        let mut vers = pkg.versions();
+        while let Some(ver) = vers.next() {
+            if ver.version() == current {
+                binary = Some(Installed {
+                    name: format!("{}:{}", pkg.name(), pkg.arch()),
+                    source: ver.source_package(),
+                    source_version: ver.source_version(),
+                    available: Vec::new(),
+                });
+            } else {
+                available.extend(available_from(&ver, opts));
+            }
+        }
+
+        if let Some(mut binary) = binary {
+            binary.available = available;
+            installed.push(binary);
+        }
+    }
+
+    installed
+}
+
+/// Where `ver` can be downloaded from, if it's an origin we want.
+fn available_from(ver: &VerView, opts: &ScanOpts) -> Option<AvailableFix> {
+    let mut ver_files = ver.origin_iter();
+    while let Some(ver_file) = ver_files.next() {
+        let origin = match Origin::from_ver_file(&ver_file) {
+            Some(origin) => origin,
+            None => continue,
+        };
+
+        if origin.index_type == "Debian dpkg status file" {
+            continue;
+        }
+
+        if opts.fix_origins.is_empty()
+            || opts
+                .fix_origins
+                .iter()
+                .any(|pattern| pattern.matches(&origin))
+        {
+            return Some(AvailableFix {
+                package: ver.package().name(),
+                version: ver.version(),
+                source_version: ver.source_version(),
+                origin,
+            });
+        }
+    }
+
+    None
+}
+
+/// The newest version available for any of the binaries which has the fix.
+fn best_fix(cache: &Cache, binaries: &[Installed], fixed: &str) -> Option<AvailableFix> {
+    binaries
+        .iter()
+        .flat_map(|binary| &binary.available)
+        .filter(|available| {
+            cache.compare_versions(&available.source_version, fixed) != cmp::Ordering::Less
+        })
+        .max_by(|left, right| cache.compare_versions(&left.version, &right.version))
+        .cloned()
+}
+
+/// e.g. `openssl 3.0.11-1~deb12u1: CVE-2024-0727 (low), fixed in 3.0.13-1~deb12u1`.
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: {}", self.source, self.source_version, self.issue)?;
+        if let Some(ref urgency) = self.urgency {
+            write!(f, " ({urgency})")?;
+        }
+        match self.fixed_version {
+            Some(ref fixed) => write!(f, ", fixed in {fixed}")?,
+            None => write!(f, ", no fix yet")?,
+        }
+        if let Some(ref fix) = self.fix {
+            write!(f, ", available from {}", fix.origin.archive)?;
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,14 @@
 //! Some structs representing basic concepts, and utilities to copy out of "iterators".
 
 use std::fmt;
+use std::str;
 
+use crate::Cache;
+use crate::error::OriginPatternError;
 use crate::sane;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BinaryPackage {
     pub name: String,
     pub arch: String,
@@ -37,6 +41,7 @@ impl fmt::Display for BinaryPackage {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Version {
     pub version: String,
     pub arch: String,
@@ -84,6 +89,7 @@ impl fmt::Display for Version {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Origin {
     pub file_name: String,
     pub archive: String,
@@ -145,7 +151,87 @@ impl fmt::Display for Origin {
     }
 }
 
+/// Which archives to trust, in unattended-upgrades' `Origins-Pattern` syntax, e.g.
+/// `origin=Debian,codename=bookworm-security,label=Debian-Security`. Every field given
+/// has to match exactly; the ones left out match anything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OriginPattern {
+    pub origin: Option<String>,
+    pub label: Option<String>,
+    /// Also known as the suite, e.g. `stable-security`.
+    pub archive: Option<String>,
+    pub codename: Option<String>,
+    pub component: Option<String>,
+    pub site: Option<String>,
+}
+
+impl OriginPattern {
+    pub fn matches(&self, origin: &Origin) -> bool {
+        let matches = |wanted: &Option<String>, actual: Option<&str>| match wanted {
+            Some(wanted) => actual == Some(wanted.as_str()),
+            None => true,
+        };
+
+        matches(&self.origin, origin.origin.as_deref())
+            && matches(&self.label, origin.label.as_deref())
+            && matches(&self.archive, Some(&origin.archive))
+            && matches(&self.codename, origin.codename.as_deref())
+            && matches(&self.component, Some(&origin.component))
+            && matches(&self.site, origin.site.as_deref())
+    }
+}
+
+/// Fields are separated by commas, which can be escaped as `\,`. The short names
+/// (`o`, `l`, `a`, `n`, `c`) are accepted too, as is `suite` for `archive`.
+impl str::FromStr for OriginPattern {
+    type Err = OriginPatternError;
+
+    fn from_str(pattern: &str) -> Result<OriginPattern, OriginPatternError> {
+        let error = |message: String| OriginPatternError {
+            pattern: pattern.to_string(),
+            message,
+        };
+
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => field.push(chars.next().unwrap_or('\\')),
+                ',' => fields.push(std::mem::take(&mut field)),
+                c => field.push(c),
+            }
+        }
+        fields.push(field);
+
+        let mut parsed = OriginPattern::default();
+        for field in fields {
+            if field.trim().is_empty() {
+                continue;
+            }
+
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| error(format!("{field:?} isn't key=value")))?;
+            let slot = match key.trim() {
+                "origin" | "o" => &mut parsed.origin,
+                "label" | "l" => &mut parsed.label,
+                "archive" | "suite" | "a" => &mut parsed.archive,
+                "codename" | "n" => &mut parsed.codename,
+                "component" | "c" => &mut parsed.component,
+                "site" => &mut parsed.site,
+                other => return Err(error(format!("unknown field {other:?}"))),
+            };
+            *slot = Some(value.to_string());
+        }
+
+        Ok(parsed)
+    }
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VersionOrigins {
     pub version: Version,
     pub origins: Vec<Origin>,
@@ -167,6 +253,7 @@ impl VersionOrigins {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BinaryPackageVersions {
     pub pkg: BinaryPackage,
     pub versions: Vec<Version>,
@@ -186,3 +273,151 @@ impl fmt::Display for BinaryPackageVersions {
         write!(f, "{} + {} versions", self.pkg, self.versions.len())
     }
 }
+
+/// A checksum of a file, as listed in `Packages` files.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hash {
+    /// As apt names them: `MD5Sum`, `SHA1`, `SHA256` or `SHA512`.
+    pub kind: String,
+    /// Lowercase hex.
+    pub value: String,
+}
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.kind, self.value)
+    }
+}
+
+/// A version's description, in one language.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Description {
+    /// e.g. `de`, or `""` for the untranslated description in the `Packages` file.
+    pub language: String,
+    /// `Description-md5`, which identifies the (English) text being translated.
+    pub md5: String,
+    pub short_desc: String,
+    pub long_desc: String,
+}
+
+/// A single dependency, as it appears in e.g. `Depends:`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dependency {
+    /// e.g. `Depends` or `Breaks`.
+    pub dep_type: String,
+    pub target_pkg: String,
+    pub target_arch: String,
+    /// e.g. `>=`, if this dependency is versioned.
+    pub comp_type: Option<String>,
+    pub target_ver: Option<String>,
+    /// Whether the next dependency is an alternative to this one.
+    pub is_or: bool,
+}
+
+impl Dependency {
+    pub fn new(view: &sane::DepView) -> Self {
+        let comp_type = view.comp_type();
+        let (comp_type, target_ver) = if comp_type.is_empty() {
+            (None, None)
+        } else {
+            (Some(comp_type), Some(view.target_ver()))
+        };
+
+        let target = view.target_pkg();
+        Dependency {
+            dep_type: view.dep_type(),
+            target_pkg: target.name(),
+            target_arch: target.arch(),
+            comp_type,
+            target_ver,
+            is_or: view.is_or(),
+        }
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}:{}",
+            self.dep_type, self.target_pkg, self.target_arch
+        )?;
+        if let (Some(comp_type), Some(target_ver)) = (&self.comp_type, &self.target_ver) {
+            write!(f, " ({comp_type} {target_ver})")?;
+        }
+        if self.is_or {
+            write!(f, " |")?;
+        }
+        Ok(())
+    }
+}
+
+/// A version's entry in a package file (a `Packages` list, or the dpkg status file):
+/// what the record says, and where it came from.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PackageFile {
+    pub origin: Option<Origin>,
+    pub short_desc: Option<String>,
+    pub long_desc: Option<String>,
+    pub maintainer: Option<String>,
+    pub homepage: Option<String>,
+}
+
+impl PackageFile {
+    pub fn new(view: &sane::VerFileView) -> Self {
+        PackageFile {
+            origin: Origin::from_ver_file(view),
+            short_desc: view.short_desc(),
+            long_desc: view.long_desc(),
+            maintainer: view.maintainer(),
+            homepage: view.homepage(),
+        }
+    }
+}
+
+/// Everything about a version: its files and its dependencies.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VersionDetails {
+    pub version: Version,
+    pub files: Vec<PackageFile>,
+    pub dependencies: Vec<Dependency>,
+}
+
+impl VersionDetails {
+    pub fn new(view: &sane::VerView) -> Self {
+        VersionDetails {
+            version: Version::new(view),
+            files: view.origin_iter().map(PackageFile::new).collect(),
+            dependencies: view.dep_iter().map(Dependency::new).collect(),
+        }
+    }
+}
+
+/// Everything about a package, and all of its versions.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PackageDetails {
+    pub pkg: BinaryPackage,
+    pub versions: Vec<VersionDetails>,
+}
+
+impl PackageDetails {
+    pub fn new(view: &sane::PkgView) -> Self {
+        PackageDetails {
+            pkg: BinaryPackage::new(view),
+            versions: view.versions().map(VersionDetails::new).collect(),
+        }
+    }
+}
+
+/// Copy out everything in the cache, e.g. to ship an inventory somewhere else.
+///
+/// This reads every record, so is pretty slow.
+pub fn snapshot(cache: &mut Cache) -> Vec<PackageDetails> {
+    cache.iter().map(PackageDetails::new).collect()
+}
@@ -0,0 +1,367 @@
+//! An owned copy of the package graph, which doesn't hold the cache lock.
+//!
+//! Everything lives in flat arenas, and refers to everything else by index, so a snapshot of
+//! a full archive is a handful of allocations instead of millions, and can be sent to
+//! another thread, or kept around across a `Cache::reload()`.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::Cache;
+use crate::sane::MultiArch;
+use crate::simple::Origin;
+
+/// A package in a `Snapshot`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PkgIndex(u32);
+
+/// A version in a `Snapshot`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VerIndex(u32);
+
+/// A package file (e.g. a `Packages` list) in a `Snapshot`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileIndex(u32);
+
+/// A package, per architecture. Virtual packages have no versions.
+#[derive(Clone, Debug)]
+pub struct Package {
+    pub name: String,
+    pub arch: String,
+    pub current: Option<VerIndex>,
+    pub candidate: Option<VerIndex>,
+    versions: Range<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Version {
+    pub package: PkgIndex,
+    pub version: String,
+    pub arch: String,
+    pub section: Option<String>,
+    pub multi_arch: MultiArch,
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub source_package: String,
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub source_version: String,
+    deps: Range<u32>,
+    provides: Range<u32>,
+    files: Range<u32>,
+}
+
+/// A dependency of `version` on `target`, as in `DepView`.
+#[derive(Clone, Debug)]
+pub struct Dependency {
+    pub version: VerIndex,
+    pub target: PkgIndex,
+    pub dep_type: String,
+    pub comp_type: Option<String>,
+    pub target_ver: Option<String>,
+    /// Whether the next dependency of `version` is an alternative to this one.
+    pub is_or: bool,
+}
+
+/// `version` says it provides `target`, as in `PrvView`.
+#[derive(Clone, Debug)]
+pub struct Provide {
+    pub version: VerIndex,
+    pub target: PkgIndex,
+    pub provide_version: Option<String>,
+}
+
+/// The whole cache, copied out in one pass.
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    packages: Vec<Package>,
+    versions: Vec<Version>,
+    deps: Vec<Dependency>,
+    provides: Vec<Provide>,
+    files: Vec<Origin>,
+    ver_files: Vec<FileIndex>,
+
+    by_name: HashMap<String, Vec<PkgIndex>>,
+
+    // reverse indexes: the entries for package `n` are `rev[offsets[n]..offsets[n + 1]]`
+    rdep_offsets: Vec<u32>,
+    rdeps: Vec<u32>,
+    provider_offsets: Vec<u32>,
+    providers: Vec<u32>,
+}
+
+/// A reference to a package we haven't necessarily seen yet.
+struct Pending {
+    name: String,
+    arch: String,
+}
+
+impl Snapshot {
+    /// Copy the package graph out of the cache. This reads every package, version and
+    /// dependency, but none of the records, so is much quicker than `simple::snapshot`.
+    pub fn new(cache: &mut Cache) -> Snapshot {
+        let mut snap = Snapshot::default();
+        let mut file_names = HashMap::new();
+        let mut dep_targets = Vec::new();
+        let mut prv_targets = Vec::new();
+
+        let mut pkgs = cache.iter();
+        while let Some(pkg) = pkgs.next() {
+            let pkg_idx = PkgIndex(to_u32(snap.packages.len()));
+            let current = pkg.current_version();
+            let candidate = pkg.candidate_version();
+            let versions_start = to_u32(snap.versions.len());
+
+            let mut vers = pkg.versions();
+            while let Some(ver) = vers.next() {
+                let ver_idx = VerIndex(to_u32(snap.versions.len()));
+
+                let deps_start = to_u32(snap.deps.len());
+                let mut deps = ver.dep_iter();
+                while let Some(dep) = deps.next() {
+                    let target = dep.target_pkg();
+                    dep_targets.push(Pending {
+                        name: target.name(),
+                        arch: target.arch(),
+                    });
+
+                    let comp_type = dep.comp_type();
+                    let (comp_type, target_ver) = if comp_type.is_empty() {
+                        (None, None)
+                    } else {
+                        (Some(comp_type), Some(dep.target_ver()))
+                    };
+
+                    snap.deps.push(Dependency {
+                        version: ver_idx,
+                        // filled in once every package has an index
+                        target: PkgIndex(u32::MAX),
+                        dep_type: dep.dep_type(),
+                        comp_type,
+                        target_ver,
+                        is_or: dep.is_or(),
+                    });
+                }
+
+                let provides_start = to_u32(snap.provides.len());
+                let mut provides = ver.provides();
+                while let Some(prv) = provides.next() {
+                    let target = prv.target_pkg();
+                    prv_targets.push(Pending {
+                        name: target.name(),
+                        arch: target.arch(),
+                    });
+
+                    snap.provides.push(Provide {
+                        version: ver_idx,
+                        target: PkgIndex(u32::MAX),
+                        provide_version: prv.provide_version(),
+                    });
+                }
+
+                let files_start = to_u32(snap.ver_files.len());
+                let mut ver_files = ver.origin_iter();
+                while let Some(ver_file) = ver_files.next() {
+                    let mut pkg_files = ver_file.file();
+                    while let Some(pkg_file) = pkg_files.next() {
+                        let name = pkg_file.file_name();
+                        let files = &mut snap.files;
+                        let idx = *file_names.entry(name).or_insert_with(|| {
+                            files.push(Origin::new(&pkg_file));
+                            FileIndex(to_u32(files.len() - 1))
+                        });
+                        snap.ver_files.push(idx);
+                    }
+                }
+
+                snap.versions.push(Version {
+                    package: pkg_idx,
+                    version: ver.version(),
+                    arch: ver.arch(),
+                    section: ver.section(),
+                    multi_arch: ver.multi_arch(),
+                    #[cfg(not(feature = "ye-olde-apt"))]
+                    source_package: ver.source_package(),
+                    #[cfg(not(feature = "ye-olde-apt"))]
+                    source_version: ver.source_version(),
+                    deps: deps_start..to_u32(snap.deps.len()),
+                    provides: provides_start..to_u32(snap.provides.len()),
+                    files: files_start..to_u32(snap.ver_files.len()),
+                });
+            }
+
+            let versions = versions_start..to_u32(snap.versions.len());
+            let find = |wanted: &Option<String>| {
+                let wanted = wanted.as_ref()?;
+                versions
+                    .clone()
+                    .find(|&idx| snap.versions[idx as usize].version == *wanted)
+                    .map(VerIndex)
+            };
+
+            let package = Package {
+                name: pkg.name(),
+                arch: pkg.arch(),
+                current: find(&current),
+                candidate: find(&candidate),
+                versions,
+            };
+
+            snap.by_name
+                .entry(package.name.clone())
+                .or_default()
+                .push(pkg_idx);
+            snap.packages.push(package);
+        }
+        drop(pkgs);
+
+        for (dep, pending) in snap.deps.iter_mut().zip(dep_targets) {
+            dep.target = resolve(&mut snap.packages, &mut snap.by_name, pending);
+        }
+
+        for (prv, pending) in snap.provides.iter_mut().zip(prv_targets) {
+            prv.target = resolve(&mut snap.packages, &mut snap.by_name, pending);
+        }
+
+        let count = snap.packages.len();
+        let (offsets, rdeps) = reverse_index(count, snap.deps.iter().map(|dep| dep.target));
+        snap.rdep_offsets = offsets;
+        snap.rdeps = rdeps;
+
+        let (offsets, providers) = reverse_index(count, snap.provides.iter().map(|prv| prv.target));
+        snap.provider_offsets = offsets;
+        snap.providers = providers;
+
+        snap
+    }
+
+    /// Every package, in cache order.
+    pub fn packages(&self) -> impl Iterator<Item = (PkgIndex, &Package)> {
+        self.packages
+            .iter()
+            .enumerate()
+            .map(|(idx, pkg)| (PkgIndex(idx as u32), pkg))
+    }
+
+    pub fn package(&self, idx: PkgIndex) -> &Package {
+        &self.packages[idx.0 as usize]
+    }
+
+    pub fn version(&self, idx: VerIndex) -> &Version {
+        &self.versions[idx.0 as usize]
+    }
+
+    pub fn file(&self, idx: FileIndex) -> &Origin {
+        &self.files[idx.0 as usize]
+    }
+
+    /// The package, in every architecture we know about.
+    pub fn find_by_name(&self, name: &str) -> &[PkgIndex] {
+        self.by_name.get(name).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn find_by_name_arch(&self, name: &str, arch: &str) -> Option<PkgIndex> {
+        self.find_by_name(name)
+            .iter()
+            .copied()
+            .find(|&idx| self.package(idx).arch == arch)
+    }
+
+    /// All the versions of a package, in the cache's order (newest first).
+    pub fn versions(&self, pkg: PkgIndex) -> impl Iterator<Item = (VerIndex, &Version)> {
+        to_usize(&self.package(pkg).versions).map(|idx| (VerIndex(idx as u32), &self.versions[idx]))
+    }
+
+    pub fn dependencies(&self, ver: VerIndex) -> &[Dependency] {
+        &self.deps[to_usize(&self.version(ver).deps)]
+    }
+
+    pub fn provides(&self, ver: VerIndex) -> &[Provide] {
+        &self.provides[to_usize(&self.version(ver).provides)]
+    }
+
+    /// Where a version can be found: the package lists it's in, and the dpkg status file
+    /// if it's installed.
+    pub fn files(&self, ver: VerIndex) -> impl Iterator<Item = (FileIndex, &Origin)> {
+        self.ver_files[to_usize(&self.version(ver).files)]
+            .iter()
+            .map(|&idx| (idx, self.file(idx)))
+    }
+
+    /// Every dependency, from any version of any package, which names this package.
+    pub fn reverse_dependencies(&self, pkg: PkgIndex) -> impl Iterator<Item = &Dependency> {
+        lookup(&self.rdep_offsets, &self.rdeps, pkg)
+            .iter()
+            .map(|&idx| &self.deps[idx as usize])
+    }
+
+    /// Every version which provides this package.
+    pub fn provided_by(&self, pkg: PkgIndex) -> impl Iterator<Item = &Provide> {
+        lookup(&self.provider_offsets, &self.providers, pkg)
+            .iter()
+            .map(|&idx| &self.provides[idx as usize])
+    }
+}
+
+fn resolve(
+    packages: &mut Vec<Package>,
+    by_name: &mut HashMap<String, Vec<PkgIndex>>,
+    pending: Pending,
+) -> PkgIndex {
+    let known = by_name.entry(pending.name.clone()).or_default();
+    if let Some(&idx) = known
+        .iter()
+        .find(|idx| packages[idx.0 as usize].arch == pending.arch)
+    {
+        return idx;
+    }
+
+    // The cache iterator visits every package, so this shouldn't happen, but
+    // it's easy enough to make up a virtual package if it does.
+    let idx = PkgIndex(to_u32(packages.len()));
+    known.push(idx);
+    let end = to_u32(packages.len());
+    packages.push(Package {
+        name: pending.name,
+        arch: pending.arch,
+        current: None,
+        candidate: None,
+        versions: end..end,
+    });
+    idx
+}
+
+/// Compressed-row style: group the (indexes of the) items by the package they point at.
+fn reverse_index(
+    count: usize,
+    targets: impl Iterator<Item = PkgIndex> + Clone,
+) -> (Vec<u32>, Vec<u32>) {
+    let mut offsets = vec![0u32; count + 1];
+    for target in targets.clone() {
+        offsets[target.0 as usize + 1] += 1;
+    }
+    for idx in 1..offsets.len() {
+        offsets[idx] += offsets[idx - 1];
+    }
+
+    let mut next = offsets.clone();
+    let mut entries = vec![0u32; offsets[count] as usize];
+    for (item, target) in targets.enumerate() {
+        let slot = &mut next[target.0 as usize];
+        entries[*slot as usize] = to_u32(item);
+        *slot += 1;
+    }
+
+    (offsets, entries)
+}
+
+fn lookup<'s>(offsets: &[u32], entries: &'s [u32], pkg: PkgIndex) -> &'s [u32] {
+    let idx = pkg.0 as usize;
+    &entries[offsets[idx] as usize..offsets[idx + 1] as usize]
+}
+
+fn to_u32(len: usize) -> u32 {
+    u32::try_from(len).expect("fewer than four billion things in the cache")
+}
+
+fn to_usize(range: &Range<u32>) -> Range<usize> {
+    range.start as usize..range.end as usize
+}
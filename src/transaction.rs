@@ -0,0 +1,342 @@
+//! Planning installs and removals with a `pkgDepCache`, like `apt-get --simulate` does,
+//! and carrying them out.
+
+use std::ffi;
+use std::fmt;
+#[cfg(not(feature = "ye-olde-apt"))]
+use std::panic;
+use std::sync::MutexGuard;
+
+#[cfg(not(feature = "ye-olde-apt"))]
+use libc::c_char;
+#[cfg(not(feature = "ye-olde-apt"))]
+use libc::c_void;
+
+use crate::error::AptError;
+use crate::raw;
+use crate::sane::make_owned_ascii_string;
+
+/// A set of changes being worked out against the cache.
+///
+/// This has its own dependency state, so marking things here doesn't affect anything else,
+/// but it holds the cache lock while it's alive.
+#[derive(Debug)]
+pub struct Transaction<'c> {
+    _cache: MutexGuard<'c, raw::CacheHolder>,
+    ptr: raw::PTransaction,
+}
+
+/// What will happen to a package.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Install,
+    Upgrade,
+    Downgrade,
+    Reinstall,
+    Remove,
+    Purge,
+    /// Anything a later apt has, which this doesn't have a name for.
+    Other,
+}
+
+/// Which machine `Transaction::upgrade()` decides phased updates for.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Phasing {
+    /// This one, by its `/etc/machine-id`, as apt does.
+    #[default]
+    Local,
+    /// Another machine, by its id, e.g. to see what a fleet will do, as `APT::Machine-ID`.
+    MachineId(String),
+    /// Take every update, as `APT::Get::Always-Include-Phased-Updates` does.
+    Ignore,
+}
+
+/// Whether `Transaction::commit()` really changes anything.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CommitMode {
+    /// Download the packages and run dpkg, which needs root.
+    Apply,
+    /// Step through the same plan, in the order dpkg would be run, without running it.
+    DryRun,
+}
+
+/// What sort of step an `Event` is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Unpack,
+    Configure,
+    Remove,
+    Trigger,
+    /// dpkg failed to process the package; the message says why.
+    Error,
+    /// dpkg wants to ask about a changed conffile. Set `Dpkg::Options` (e.g.
+    /// `--force-confold`) if there's nobody at the terminal to answer it.
+    ConffilePrompt,
+    /// Anything else apt reports, like running dpkg at all.
+    Other,
+}
+
+/// A step of `Transaction::commit()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    /// Empty for steps which aren't about a single package.
+    pub package: String,
+    /// How far through it is; `total` can grow as dpkg goes.
+    pub done: u32,
+    pub total: u32,
+    /// apt's description of the step, which may be translated.
+    pub message: String,
+}
+
+/// A package which the transaction would change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub name: String,
+    pub arch: String,
+    pub action: Action,
+    pub current_version: Option<String>,
+    /// The version that will be installed, unless this is a removal.
+    pub new_version: Option<String>,
+}
+
+impl<'c> Transaction<'c> {
+    pub(crate) fn new(cache: MutexGuard<'c, raw::CacheHolder>) -> Result<Self, AptError> {
+        let ptr = unsafe { raw::transaction_create(cache.ptr) };
+        if ptr.is_null() {
+            return Err(AptError::take("couldn't initialise the dependency cache"));
+        }
+
+        Ok(Transaction { _cache: cache, ptr })
+    }
+
+    /// Pick which version of `name` will be installed, instead of the policy's candidate.
+    ///
+    /// Returns false if there's no such package or version.
+    pub fn set_candidate(&mut self, name: &str, version: &str) -> bool {
+        let name = ffi::CString::new(name).unwrap();
+        let version = ffi::CString::new(version).unwrap();
+        unsafe { raw::transaction_set_candidate(self.ptr, name.as_ptr(), version.as_ptr()) }
+    }
+
+    /// Mark a package (`name` or `name:arch`) for installation, along with its dependencies.
+    ///
+    /// Returns false if there's no such package.
+    pub fn mark_install(&mut self, name: &str) -> bool {
+        let name = ffi::CString::new(name).unwrap();
+        unsafe { raw::transaction_mark_install(self.ptr, name.as_ptr()) }
+    }
+
+    /// Mark a package for removal, or purging.
+    ///
+    /// Returns false if there's no such package.
+    pub fn mark_remove(&mut self, name: &str, purge: bool) -> bool {
+        let name = ffi::CString::new(name).unwrap();
+        unsafe { raw::transaction_mark_remove(self.ptr, name.as_ptr(), purge) }
+    }
+
+    /// Run the problem resolver, as `apt-get install` would, to fix up any broken packages.
+    pub fn resolve(&mut self) -> Result<(), AptError> {
+        if unsafe { raw::transaction_resolve(self.ptr) } {
+            Ok(())
+        } else {
+            Err(AptError::take("unable to correct problems"))
+        }
+    }
+
+    /// Mark everything upgradable for upgrade, like `apt-get upgrade`: packages which would
+    /// need something removed or newly installed are kept back, as are phased updates which
+    /// `phasing` says the machine isn't in yet.
+    ///
+    /// Returns the packages held back by phasing, as `name:arch`; see `changes()` for the rest.
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn upgrade(&mut self, phasing: &Phasing) -> Result<Vec<String>, AptError> {
+        let (machine_id, include_all) = phasing.to_raw();
+        let machine_id = machine_id
+            .as_ref()
+            .map_or(std::ptr::null(), |id| id.as_ptr());
+
+        if !unsafe { raw::transaction_upgrade(self.ptr, machine_id, include_all) } {
+            return Err(AptError::take("unable to calculate the upgrade"));
+        }
+
+        unsafe {
+            Ok((0..raw::transaction_held_back(self.ptr))
+                .map(|idx| {
+                    let name =
+                        make_owned_ascii_string(raw::transaction_held_back_name(self.ptr, idx))
+                            .expect("packages always have names");
+                    let arch =
+                        make_owned_ascii_string(raw::transaction_held_back_arch(self.ptr, idx))
+                            .expect("packages always have architectures");
+                    format!("{name}:{arch}")
+                })
+                .collect())
+        }
+    }
+
+    /// Carry out the changes: download what's needed and run dpkg, as `apt-get install`
+    /// does, reporting each step to `progress`. The transaction has to be free of broken
+    /// packages; `resolve()` it first.
+    ///
+    /// The cache is out of date afterwards: `Cache::reload()` it.
+    #[cfg(not(feature = "ye-olde-apt"))]
+    pub fn commit<F>(self, mode: CommitMode, mut progress: F) -> Result<(), AptError>
+    where
+        F: FnMut(&Event),
+    {
+        let mut progress = Progress {
+            callback: &mut progress,
+            panic: None,
+        };
+        let user_data = &mut progress as *mut Progress as *mut c_void;
+        let dry_run = mode == CommitMode::DryRun;
+
+        // CommitResult, in lib.cpp
+        let result =
+            match unsafe { raw::transaction_commit(self.ptr, dry_run, commit_event, user_data) } {
+                0 => Ok(()),
+                -1 => Err(AptError::take("couldn't start the installation")),
+                2 => Err(AptError::take("the installation was left incomplete")),
+                _ => Err(AptError::take("the installation failed")),
+            };
+
+        // unwinding through apt isn't allowed, so `progress` panics are carried over it;
+        // the cache lock is let go of first, so the panic doesn't poison it
+        if let Some(payload) = progress.panic {
+            drop(self);
+            panic::resume_unwind(payload);
+        }
+        result
+    }
+
+    /// How many packages would be left with unsatisfied dependencies.
+    pub fn broken_count(&self) -> u32 {
+        unsafe { raw::transaction_broken_count(self.ptr) }
+    }
+
+    /// Everything that is marked to change, in cache order.
+    pub fn changes(&self) -> Vec<Change> {
+        unsafe {
+            (0..raw::transaction_changes(self.ptr))
+                .map(|idx| Change {
+                    name: make_owned_ascii_string(raw::transaction_change_name(self.ptr, idx))
+                        .expect("packages always have names"),
+                    arch: make_owned_ascii_string(raw::transaction_change_arch(self.ptr, idx))
+                        .expect("packages always have architectures"),
+                    action: action(raw::transaction_change_action(self.ptr, idx)),
+                    current_version: make_owned_ascii_string(
+                        raw::transaction_change_current_version(self.ptr, idx),
+                    ),
+                    new_version: make_owned_ascii_string(raw::transaction_change_new_version(
+                        self.ptr, idx,
+                    )),
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(not(feature = "ye-olde-apt"))]
+impl Phasing {
+    /// As lib.cpp takes it: any `APT::Machine-ID` to set, and whether to set
+    /// `APT::Get::Always-Include-Phased-Updates`.
+    pub(crate) fn to_raw(&self) -> (Option<ffi::CString>, bool) {
+        match *self {
+            Phasing::Local => (None, false),
+            Phasing::MachineId(ref id) => (Some(ffi::CString::new(id.as_str()).unwrap()), false),
+            Phasing::Ignore => (None, true),
+        }
+    }
+}
+
+impl<'c> Drop for Transaction<'c> {
+    fn drop(&mut self) {
+        unsafe { raw::transaction_release(self.ptr) }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {}:{}", self.action, self.name, self.arch)?;
+        if let Some(ref version) = self.current_version {
+            write!(f, " {version}")?;
+        }
+        if let Some(ref version) = self.new_version {
+            write!(f, " -> {version}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The `user_data` for `commit_event`.
+#[cfg(not(feature = "ye-olde-apt"))]
+struct Progress<'a> {
+    callback: &'a mut dyn FnMut(&Event),
+    /// What the callback panicked with; it isn't called again after that.
+    panic: Option<Box<dyn std::any::Any + Send>>,
+}
+
+/// The `raw::CommitCallback` for `Transaction::commit()`.
+#[cfg(not(feature = "ye-olde-apt"))]
+unsafe extern "C" fn commit_event(
+    user_data: *mut c_void,
+    kind: i32,
+    package: *const c_char,
+    done: u32,
+    total: u32,
+    message: *const c_char,
+) {
+    let progress = unsafe { &mut *(user_data as *mut Progress) };
+    if progress.panic.is_some() {
+        return;
+    }
+
+    // apt's messages are translated, so needn't be ascii
+    let (package, message) = unsafe {
+        (
+            ffi::CStr::from_ptr(package).to_string_lossy().into_owned(),
+            ffi::CStr::from_ptr(message).to_string_lossy().into_owned(),
+        )
+    };
+
+    let event = Event {
+        kind: event_kind(kind),
+        package,
+        done,
+        total,
+        message,
+    };
+
+    let callback = &mut progress.callback;
+    if let Err(payload) = panic::catch_unwind(panic::AssertUnwindSafe(|| callback(&event))) {
+        progress.panic = Some(payload);
+    }
+}
+
+#[cfg(not(feature = "ye-olde-apt"))]
+fn event_kind(raw: i32) -> EventKind {
+    // CommitEvent, in lib.cpp
+    match raw {
+        0 => EventKind::Unpack,
+        1 => EventKind::Configure,
+        2 => EventKind::Remove,
+        3 => EventKind::Trigger,
+        4 => EventKind::Error,
+        5 => EventKind::ConffilePrompt,
+        _ => EventKind::Other,
+    }
+}
+
+fn action(raw: i32) -> Action {
+    // ChangeAction, in lib.cpp
+    match raw {
+        0 => Action::Install,
+        1 => Action::Upgrade,
+        2 => Action::Downgrade,
+        3 => Action::Reinstall,
+        4 => Action::Remove,
+        5 => Action::Purge,
+        _ => Action::Other,
+    }
+}
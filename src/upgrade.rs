@@ -0,0 +1,99 @@
+//! What `apt list --upgradable` shows, as data, with where each upgrade would come from,
+//! so security updates can be told apart from the rest, as unattended-upgrades does.
+
+use std::fs;
+
+use crate::Cache;
+use crate::dpkg;
+use crate::sane::VerView;
+use crate::simple::Origin;
+use crate::simple::OriginPattern;
+#[cfg(not(feature = "ye-olde-apt"))]
+use crate::transaction::Phasing;
+
+/// An installed package whose candidate is a different version.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Upgradable {
+    pub name: String,
+    pub arch: String,
+    pub installed_version: String,
+    pub candidate_version: String,
+    /// The archives the candidate can be downloaded from, e.g. `bookworm-security`.
+    pub origins: Vec<Origin>,
+    /// Whether any of `origins` matches one of the security patterns.
+    pub security: bool,
+    /// The candidate's `Phased-Update-Percentage`, if it's being phased in.
+    /// Always `None` with `ye-olde-apt`, which predates phasing.
+    pub phased_update_percentage: Option<u8>,
+    /// Whether apt would keep the candidate back, as this machine isn't in its phase yet,
+    /// i.e. it isn't `VerView::is_phased_in` for `Phasing::Local`.
+    pub held_back: bool,
+}
+
+/// For `Cache::upgradable()`.
+pub(crate) fn upgradable(cache: &mut Cache, security: &[OriginPattern]) -> Vec<Upgradable> {
+    let mut found = Vec::new();
+
+    let mut pkgs = cache.iter();
+    while let Some(pkg) = pkgs.next() {
+        let (installed_version, candidate_version) =
+            match (pkg.current_version(), pkg.candidate_version()) {
+                (Some(installed), Some(candidate)) if installed != candidate => {
+                    (installed, candidate)
+                }
+                _ => continue,
+            };
+
+        let mut vers = pkg.versions();
+        while let Some(ver) = vers.next() {
+            if ver.version() != candidate_version {
+                continue;
+            }
+
+            let origins = origins(&ver);
+            #[cfg(not(feature = "ye-olde-apt"))]
+            let (phased_update_percentage, held_back) = (
+                ver.phased_update_percentage(),
+                !ver.is_phased_in(&Phasing::Local),
+            );
+            #[cfg(feature = "ye-olde-apt")]
+            let (phased_update_percentage, held_back) = (None, false);
+
+            found.push(Upgradable {
+                name: pkg.name(),
+                arch: pkg.arch(),
+                installed_version,
+                candidate_version,
+                security: origins
+                    .iter()
+                    .any(|origin| security.iter().any(|pattern| pattern.matches(origin))),
+                origins,
+                phased_update_percentage,
+                held_back,
+            });
+            break;
+        }
+    }
+
+    found
+}
+
+/// Where `ver` can be downloaded from.
+fn origins(ver: &VerView) -> Vec<Origin> {
+    ver.origin_iter()
+        .filter_map(|ver_file| Origin::from_ver_file(ver_file))
+        .filter(|origin| origin.index_type != "Debian dpkg status file")
+        .collect()
+}
+
+/// `Cache::machine_id`; the caller must hold the cache lock.
+pub(crate) fn machine_id() -> Option<String> {
+    let id = fs::read_to_string(dpkg::root_dir().join("etc/machine-id")).ok()?;
+    let id = id.trim();
+    if id.is_empty() {
+        None
+    } else {
+        Some(id.to_string())
+    }
+}
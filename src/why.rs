@@ -0,0 +1,259 @@
+//! Explaining why a package is installed, or why it can't be, like `aptitude why` and
+//! `aptitude why-not`.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::Cache;
+use crate::closure::Edge;
+use crate::sane::PackageId;
+use crate::sane::PkgView;
+use crate::sane::VerView;
+use crate::sane::VersionId;
+
+/// A version of a package, by name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageVersion {
+    pub name: String,
+    pub arch: String,
+    pub version: String,
+}
+
+/// An installed version, and its dependency on the next step of a `Chain`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub package: PackageVersion,
+    pub edge: Edge,
+}
+
+/// How a package installed by hand ends up needing another. Each step's dependency is
+/// satisfied by the package in the next step, or, for the last step, the one asked about,
+/// either directly, or through something it provides.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Chain {
+    /// Empty if the package was itself installed by hand.
+    pub steps: Vec<Step>,
+}
+
+/// A `Conflicts` or `Breaks` between an installed version and a candidate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// The version with the dependency.
+    pub package: PackageVersion,
+    pub edge: Edge,
+    /// The version it rules out.
+    pub against: PackageVersion,
+}
+
+/// For `Cache::why()`: breadth first, backwards from `name`, through the installed
+/// versions which depend on it, stopping at each package installed by hand.
+pub(crate) fn why(cache: &mut Cache, name: &str) -> Vec<Chain> {
+    let target = match find(cache, name) {
+        Some(target) => target,
+        None => return Vec::new(),
+    };
+
+    // for each package reached, the step it takes towards the target
+    let mut reached: HashMap<PackageId, Option<(Step, PackageId)>> = HashMap::new();
+    reached.insert(target, None);
+    let mut queue = VecDeque::from([target]);
+    let mut roots = Vec::new();
+
+    while let Some(id) = queue.pop_front() {
+        let mut found = cache.package_by_id(id);
+        let pkg = found.next().expect("reached packages are in the cache");
+        let current = match pkg.current_version() {
+            Some(current) => current,
+            None => continue,
+        };
+
+        if !pkg.is_auto_installed() {
+            roots.push(id);
+            continue;
+        }
+
+        let mut needed_by = Vec::new();
+        let mut vers = pkg.versions();
+        while let Some(ver) = vers.next() {
+            if ver.version() != current {
+                continue;
+            }
+
+            dependents(&pkg, ver.id(), &mut needed_by);
+            let mut provides = ver.provides();
+            while let Some(prv) = provides.next() {
+                dependents(&prv.target_pkg(), ver.id(), &mut needed_by);
+            }
+        }
+
+        for (owner, step) in needed_by {
+            reached.entry(owner).or_insert_with(|| {
+                queue.push_back(owner);
+                Some((step, id))
+            });
+        }
+    }
+
+    roots
+        .into_iter()
+        .map(|root| {
+            let mut chain = Chain::default();
+            let mut at = root;
+            while let Some((step, next)) = reached[&at].clone() {
+                chain.steps.push(step);
+                at = next;
+            }
+            chain
+        })
+        .collect()
+}
+
+/// For `Cache::why_not()`: the conflicts between `name`'s candidate and what's installed,
+/// in either direction.
+pub(crate) fn why_not(cache: &mut Cache, name: &str) -> Vec<Conflict> {
+    let target = match find(cache, name) {
+        Some(target) => target,
+        None => return Vec::new(),
+    };
+
+    let mut found = cache.package_by_id(target);
+    let pkg = found.next().expect("found packages are in the cache");
+    let candidate = match pkg.candidate_version() {
+        Some(candidate) => candidate,
+        None => return Vec::new(),
+    };
+
+    let mut conflicts = Vec::new();
+    let mut vers = pkg.versions();
+    while let Some(ver) = vers.next() {
+        if ver.version() != candidate {
+            continue;
+        }
+
+        let mut deps = ver.dep_iter();
+        while let Some(dep) = deps.next() {
+            if !is_conflict(&dep.dep_type()) {
+                continue;
+            }
+
+            let mut targets = dep.all_targets();
+            while let Some(other) = targets.next() {
+                if is_installed(&other) {
+                    conflicts.push(Conflict {
+                        package: describe(&ver),
+                        edge: Edge::new(ver.id(), &dep),
+                        against: describe(&other),
+                    });
+                }
+            }
+        }
+
+        blockers(&pkg, &ver, &mut conflicts);
+        let mut provides = ver.provides();
+        while let Some(prv) = provides.next() {
+            blockers(&prv.target_pkg(), &ver, &mut conflicts);
+        }
+        break;
+    }
+
+    conflicts
+}
+
+/// `name`, or `name:arch`, as dpkg writes them.
+fn find(cache: &mut Cache, name: &str) -> Option<PackageId> {
+    let mut found = match name.split_once(':') {
+        Some((name, arch)) => cache.find_by_name_arch(name, arch),
+        None => cache.find_by_name(name),
+    };
+    found.next().map(|pkg| pkg.id())
+}
+
+fn is_installed(ver: &VerView) -> bool {
+    ver.package().current_version() == Some(ver.version())
+}
+
+fn is_conflict(dep_type: &str) -> bool {
+    matches!(dep_type, "Conflicts" | "Breaks")
+}
+
+/// The installed versions which need `current`, through a dependency naming `target`.
+fn dependents(target: &PkgView, current: VersionId, needed_by: &mut Vec<(PackageId, Step)>) {
+    let mut deps = target.rev_dep_iter();
+    while let Some(dep) = deps.next() {
+        if !matches!(
+            dep.dep_type().as_str(),
+            "Depends" | "PreDepends" | "Recommends"
+        ) {
+            continue;
+        }
+
+        let parent = dep.parent_ver();
+        if !is_installed(&parent) || !dep.all_targets().any(|ver| ver.id() == current) {
+            continue;
+        }
+
+        needed_by.push((
+            parent.package().id(),
+            Step {
+                package: describe(&parent),
+                edge: Edge::new(parent.id(), &dep),
+            },
+        ));
+    }
+}
+
+/// The installed versions which rule out `ver`, through a dependency naming `target`.
+fn blockers(target: &PkgView, ver: &VerView, conflicts: &mut Vec<Conflict>) {
+    let mut deps = target.rev_dep_iter();
+    while let Some(dep) = deps.next() {
+        if !is_conflict(&dep.dep_type()) {
+            continue;
+        }
+
+        let parent = dep.parent_ver();
+        if !is_installed(&parent) || !dep.all_targets().any(|other| other.id() == ver.id()) {
+            continue;
+        }
+
+        conflicts.push(Conflict {
+            package: describe(&parent),
+            edge: Edge::new(parent.id(), &dep),
+            against: describe(ver),
+        });
+    }
+}
+
+fn describe(ver: &VerView) -> PackageVersion {
+    PackageVersion {
+        name: ver.package().name(),
+        arch: ver.arch(),
+        version: ver.version(),
+    }
+}
+
+impl fmt::Display for PackageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{} {}", self.name, self.arch, self.version)
+    }
+}
+
+/// Like `aptitude why`: a line per step.
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{} {}", step.package, step.edge)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}, against {}",
+            self.package, self.edge, self.against
+        )
+    }
+}
@@ -0,0 +1,80 @@
+//! `CommitMode::Apply`, all the way to running dpkg, in a pretend system.
+//!
+//! This is its own test binary, as apt only reads `APT_CONFIG` as it starts up, and that's
+//! how it's told to show what it would run, instead of running dpkg.
+
+#![cfg(not(feature = "ye-olde-apt"))]
+
+use std::fs;
+
+use apt_pkg_native::CacheBuilder;
+use apt_pkg_native::transaction::CommitMode;
+
+mod common;
+use common::build_test_deb;
+
+#[test]
+fn apply() {
+    let dir = std::env::temp_dir().join(format!("apt-pkg-native-commit-{}", std::process::id()));
+    let root = dir.join("root");
+    let _ = fs::remove_dir_all(&dir);
+    for sub in [
+        "etc/apt/sources.list.d",
+        "var/lib/apt/lists/partial",
+        "var/cache/apt/archives/partial",
+        "var/lib/dpkg/info",
+        "var/lib/dpkg/updates",
+        "var/log/apt",
+    ] {
+        fs::create_dir_all(root.join(sub)).unwrap();
+    }
+
+    // the test package depends on dpkg
+    let status = root.join("var/lib/dpkg/status");
+    fs::write(
+        &status,
+        "Package: dpkg\n\
+         Status: install ok installed\n\
+         Maintainer: Nobody <nobody@example.com>\n\
+         Architecture: all\n\
+         Version: 1.0\n\
+         Description: test dpkg\n",
+    )
+    .unwrap();
+
+    // print dpkg's command lines instead of running them, and none of this system's hooks
+    let config = dir.join("apt.conf");
+    fs::write(
+        &config,
+        "Debug::pkgDPkgPM \"true\";\n\
+         #clear DPkg::Pre-Invoke;\n\
+         #clear DPkg::Post-Invoke;\n\
+         #clear DPkg::Pre-Install-Pkgs;\n",
+    )
+    .unwrap();
+    // SAFETY: this is the only test in this binary, and nothing has started apt yet
+    unsafe { std::env::set_var("APT_CONFIG", &config) };
+
+    let name = "apt-pkg-native-applied";
+    let deb = build_test_deb(name, "0.1-1");
+    let mut cache = CacheBuilder::new()
+        .with_root(&root)
+        .with_deb_file(&deb)
+        .build()
+        .unwrap();
+
+    {
+        let mut transaction = cache.transaction().unwrap();
+        assert!(transaction.mark_install(name));
+        transaction.resolve().unwrap();
+        transaction.commit(CommitMode::Apply, |_| {}).unwrap();
+    }
+
+    // dpkg wasn't really run, so nothing's changed
+    assert!(!fs::read_to_string(&status).unwrap().contains(name));
+    assert!(!root.join(format!("usr/bin/{name}")).exists());
+
+    CacheBuilder::new().build().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    fs::remove_dir_all(deb.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,65 @@
+//! Helpers shared by the integration tests.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Build a tiny package with `dpkg-deb`, returning the path to the `.deb`.
+pub fn build_test_deb(name: &str, version: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+
+    let dir = std::env::temp_dir().join(format!(
+        "apt-pkg-native-test-{}-{}-{}",
+        std::process::id(),
+        name,
+        version
+    ));
+    let root = dir.join("root");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(root.join("DEBIAN")).unwrap();
+    fs::create_dir_all(root.join("usr/bin")).unwrap();
+    fs::create_dir_all(root.join("etc")).unwrap();
+
+    fs::write(
+        root.join("DEBIAN/control"),
+        format!(
+            "Package: {name}\n\
+             Version: {version}\n\
+             Architecture: all\n\
+             Maintainer: Nobody <nobody@example.com>\n\
+             Depends: dpkg\n\
+             Description: test package\n \
+             A package built by the apt-pkg-native tests.\n"
+        ),
+    )
+    .unwrap();
+    fs::write(root.join("DEBIAN/conffiles"), format!("/etc/{name}.conf\n")).unwrap();
+    fs::write(root.join("DEBIAN/postinst"), "#!/bin/sh\nexit 0\n").unwrap();
+    fs::set_permissions(
+        root.join("DEBIAN/postinst"),
+        fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+    fs::write(root.join(format!("etc/{name}.conf")), "setting=1\n").unwrap();
+    fs::write(
+        root.join(format!("usr/bin/{name}")),
+        "#!/bin/sh\necho hello\n",
+    )
+    .unwrap();
+    fs::set_permissions(
+        root.join(format!("usr/bin/{name}")),
+        fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+
+    let deb = dir.join(format!("{name}_{version}_all.deb"));
+    let status = Command::new("dpkg-deb")
+        .args(["--root-owner-group", "--build"])
+        .arg(&root)
+        .arg(&deb)
+        .output()
+        .expect("dpkg-deb is needed to build test packages")
+        .status;
+    assert!(status.success());
+    deb
+}
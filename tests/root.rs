@@ -0,0 +1,631 @@
+//! Rebuilding the cache with `CacheBuilder`: with local `.deb`s, other architectures, or
+//! looking at another system with `with_root`.
+//!
+//! These are separate from the unit tests as they replace the whole cache, and the unit
+//! tests expect to see this system's packages.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+use apt_pkg_native::Cache;
+use apt_pkg_native::CacheBuilder;
+use apt_pkg_native::dpkg::FileStatus;
+use apt_pkg_native::simple::OriginPattern;
+#[cfg(not(feature = "ye-olde-apt"))]
+use apt_pkg_native::transaction::Action;
+#[cfg(not(feature = "ye-olde-apt"))]
+use apt_pkg_native::transaction::Phasing;
+
+mod common;
+use common::build_test_deb;
+
+/// Make a pretend system with `name` installed, and its `/usr/bin/shared` diverted by `other`.
+fn build_root(name: &str, other: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!(
+        "apt-pkg-native-root-{}-{}",
+        std::process::id(),
+        name
+    ));
+    let _ = fs::remove_dir_all(&root);
+    for dir in [
+        "etc/apt/sources.list.d",
+        "var/lib/apt/lists/partial",
+        "var/cache/apt/archives/partial",
+        "var/lib/dpkg/info",
+        "usr/bin",
+    ] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+
+    let dpkg = root.join("var/lib/dpkg");
+    fs::write(
+        dpkg.join("status"),
+        format!(
+            "Package: {name}\n\
+             Status: install ok installed\n\
+             Priority: optional\n\
+             Section: misc\n\
+             Installed-Size: 1\n\
+             Maintainer: Nobody <nobody@example.com>\n\
+             Architecture: all\n\
+             Version: 1.0\n\
+             Conffiles:\n \
+             /etc/{name}.conf 7d43cb06abb8273056a580aca18d8acb\n\
+             Description: test package\n\
+             \n\
+             Package: {other}\n\
+             Status: install ok installed\n\
+             Maintainer: Nobody <nobody@example.com>\n\
+             Architecture: all\n\
+             Version: 2.0\n\
+             Description: test diverter\n"
+        ),
+    )
+    .unwrap();
+    fs::write(
+        dpkg.join(format!("info/{name}.list")),
+        format!("/.\n/etc\n/etc/{name}.conf\n/usr\n/usr/bin\n/usr/bin/{name}\n/usr/bin/shared\n"),
+    )
+    .unwrap();
+    fs::write(
+        dpkg.join(format!("info/{other}.list")),
+        "/.\n/usr\n/usr/bin\n/usr/bin/shared\n",
+    )
+    .unwrap();
+    fs::write(
+        dpkg.join("diversions"),
+        format!("/usr/bin/shared\n/usr/bin/shared.{name}\n{other}\n"),
+    )
+    .unwrap();
+
+    // the conffile is as shipped, the binary has been changed, and the diverted file is gone
+    fs::write(
+        dpkg.join(format!("info/{name}.md5sums")),
+        format!(
+            "d604a220708aa59433ba410986cd4ffa  usr/bin/{name}\n\
+             d41d8cd98f00b204e9800998ecf8427e  usr/bin/shared\n"
+        ),
+    )
+    .unwrap();
+    fs::write(root.join(format!("etc/{name}.conf")), "setting=1\n").unwrap();
+    fs::write(
+        root.join(format!("usr/bin/{name}")),
+        "#!/bin/sh\necho goodbye\n",
+    )
+    .unwrap();
+
+    root
+}
+
+/// Put an archive's lists under the root, as if `apt update` had fetched them, with `release`
+/// added to its `Release` file, and `packages` as its `Packages` for the native architecture.
+fn add_archive(root: &Path, suite: &str, release: &str, packages: &str) {
+    let arch = Cache::get_singleton().native_arch();
+    let lists = root.join("var/lib/apt/lists");
+    let prefix = format!("archive.example.com_debian_dists_{suite}");
+    fs::write(
+        root.join(format!("etc/apt/sources.list.d/{suite}.list")),
+        format!("deb http://archive.example.com/debian {suite} main\n"),
+    )
+    .unwrap();
+    fs::write(
+        lists.join(format!("{prefix}_Release")),
+        format!("Suite: {suite}\nCodename: {suite}\nComponents: main\n{release}"),
+    )
+    .unwrap();
+    fs::write(
+        lists.join(format!("{prefix}_main_binary-{arch}_Packages")),
+        packages,
+    )
+    .unwrap();
+}
+
+/// A `Packages` stanza, with any `extra` fields.
+fn package(name: &str, version: &str, extra: &str) -> String {
+    format!(
+        "Package: {name}\n\
+         Version: {version}\n\
+         Architecture: all\n\
+         Maintainer: Nobody <nobody@example.com>\n\
+         Filename: pool/main/{name}_{version}_all.deb\n\
+         Size: 1000\n\
+         {extra}\
+         Description: test package\n\n"
+    )
+}
+
+/// Held by every test which replaces the singleton.
+fn rebuild_lock() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[test]
+fn installed_files() {
+    let _rebuilding = rebuild_lock();
+    let root = build_root("rooted", "diverter");
+    let mut cache = CacheBuilder::new().with_root(&root).build().unwrap();
+    {
+        let mut found = cache.find_by_name("rooted");
+        let pkg = found.next().expect("from the root's status file");
+        assert_eq!(
+            vec![
+                "/.",
+                "/etc",
+                "/etc/rooted.conf",
+                "/usr",
+                "/usr/bin",
+                "/usr/bin/rooted",
+                "/usr/bin/shared.rooted",
+            ]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>(),
+            pkg.installed_files().unwrap()
+        );
+    }
+    assert!(cache.find_by_name("dpkg").next().is_none());
+
+    let owners = |path: &str| cache.package_owning(Path::new(path)).unwrap();
+    assert_eq!(vec!["rooted"], owners("/usr/bin/rooted"));
+    assert_eq!(vec!["diverter"], owners("/usr/bin/shared"));
+    assert_eq!(vec!["rooted"], owners("/usr/bin/shared.rooted"));
+    assert_eq!(vec!["diverter", "rooted"], owners("/usr/bin"));
+
+    let cache = CacheBuilder::new().build().unwrap();
+    assert!(
+        cache
+            .package_owning("/usr/bin/dpkg")
+            .unwrap()
+            .contains(&"dpkg".to_string())
+    );
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn failed_build() {
+    let _rebuilding = rebuild_lock();
+    let root = build_root("kept", "diverter");
+    let other = build_root("abandoned", "diverter");
+    let cache = CacheBuilder::new().with_root(&root).build().unwrap();
+
+    assert!(
+        CacheBuilder::new()
+            .with_root(&other)
+            .with_deb_file(other.join("missing.deb"))
+            .build()
+            .is_err()
+    );
+
+    // still looking at the first root, for both the cache and the dpkg database
+    assert_eq!(
+        vec!["kept"],
+        cache.package_owning(Path::new("/usr/bin/kept")).unwrap()
+    );
+    assert!(
+        cache
+            .package_owning(Path::new("/usr/bin/abandoned"))
+            .unwrap()
+            .is_empty()
+    );
+
+    CacheBuilder::new().build().unwrap();
+    fs::remove_dir_all(&root).unwrap();
+    fs::remove_dir_all(&other).unwrap();
+}
+
+#[test]
+fn verify() {
+    let _rebuilding = rebuild_lock();
+    let root = build_root("verified", "diverter");
+    let mut cache = CacheBuilder::new().with_root(&root).build().unwrap();
+    {
+        let mut found = cache.find_by_name("verified");
+        let pkg = found.next().expect("from the root's status file");
+        let checks = pkg.verify().unwrap();
+        let summary = checks
+            .iter()
+            .map(|check| {
+                (
+                    check.path.to_str().unwrap(),
+                    check.conffile,
+                    check.status.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("/usr/bin/verified", false, FileStatus::Modified),
+                ("/usr/bin/shared.verified", false, FileStatus::Missing),
+                ("/etc/verified.conf", true, FileStatus::Ok),
+            ],
+            summary
+        );
+    }
+    {
+        let mut found = cache.find_by_name("diverter");
+        let pkg = found.next().expect("from the root's status file");
+        assert!(pkg.verify().unwrap().is_empty());
+    }
+
+    CacheBuilder::new().build().unwrap();
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(not(feature = "ye-olde-apt"))]
+#[test]
+fn changelog() {
+    let _rebuilding = rebuild_lock();
+    let root = build_root("logged", "diverter");
+    let doc = root.join("usr/share/doc/logged");
+    fs::create_dir_all(&doc).unwrap();
+    fs::write(
+        doc.join("changelog.Debian"),
+        "logged (1.0) unstable; urgency=medium\n",
+    )
+    .unwrap();
+
+    let mut cache = CacheBuilder::new().with_root(&root).build().unwrap();
+    let uri = {
+        let mut found = cache.find_by_name("logged");
+        let pkg = found.next().expect("from the root's status file");
+        let mut versions = pkg.versions();
+        let ver = versions.next().expect("installed");
+        ver.changelog_uri()
+            .expect("the installed version has a local changelog")
+    };
+    let local = root
+        .canonicalize()
+        .unwrap()
+        .join("usr/share/doc/logged/changelog.Debian");
+    assert_eq!(format!("copy://{}", local.display()), uri);
+    {
+        let mut found = cache.find_by_name("diverter");
+        let pkg = found.next().expect("from the root's status file");
+        let mut versions = pkg.versions();
+        let ver = versions.next().expect("installed");
+        assert_eq!(None, ver.changelog_uri());
+    }
+
+    // apt's acquire methods are found under the root, so fetch from the real system
+    CacheBuilder::new().build().unwrap();
+    let fetched = apt_pkg_native::changelog::fetch(&uri).unwrap();
+    assert!(fetched.starts_with("logged (1.0)"));
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn copyright() {
+    let _rebuilding = rebuild_lock();
+    let root = build_root("licensed", "diverter");
+    let doc = root.join("usr/share/doc");
+    fs::create_dir_all(doc.join("diverter")).unwrap();
+    fs::write(
+        doc.join("diverter/copyright"),
+        "Format: https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/\n\
+         \n\
+         Files: *\n\
+         Copyright: 2024 Nobody\n\
+         License: Expat\n",
+    )
+    .unwrap();
+    // as with `dh_installdocs --link-doc`, but absolute, so it has to stay inside the root
+    std::os::unix::fs::symlink("/usr/share/doc/diverter", doc.join("licensed")).unwrap();
+
+    let mut cache = CacheBuilder::new().with_root(&root).build().unwrap();
+    {
+        let mut found = cache.find_by_name("licensed");
+        let pkg = found.next().expect("from the root's status file");
+        let copyright = pkg.copyright().unwrap().expect("linked to diverter's");
+        assert_eq!(Some("MIT".to_string()), copyright.spdx_expression());
+    }
+
+    CacheBuilder::new().build().unwrap();
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn security_updates() {
+    let _rebuilding = rebuild_lock();
+    let root = build_root("patched", "diverter");
+    add_archive(
+        &root,
+        "stable",
+        "Origin: Example\nLabel: Example\n",
+        &package("diverter", "2.1", ""),
+    );
+    add_archive(
+        &root,
+        "stable-security",
+        "Origin: Example\nLabel: Example-Security\n",
+        &package("patched", "1.1", ""),
+    );
+
+    let mut cache = CacheBuilder::new().with_root(&root).build().unwrap();
+    let security: OriginPattern = "origin=Example,label=Example-Security".parse().unwrap();
+    let mut upgradable = cache.upgradable(&[security]);
+    upgradable.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(
+        vec![("diverter", "2.1", false), ("patched", "1.1", true)],
+        upgradable
+            .iter()
+            .map(|upgrade| (
+                upgrade.name.as_str(),
+                upgrade.candidate_version.as_str(),
+                upgrade.security
+            ))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!("stable-security", upgradable[1].origins[0].archive);
+
+    // everything's from Example, so that alone would make both security updates
+    let origin: OriginPattern = "origin=Example".parse().unwrap();
+    assert!(
+        cache
+            .upgradable(&[origin])
+            .iter()
+            .all(|upgrade| upgrade.security)
+    );
+
+    CacheBuilder::new().build().unwrap();
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(not(feature = "ye-olde-apt"))]
+#[test]
+fn phasing() {
+    let _rebuilding = rebuild_lock();
+    let root = build_root("phased", "diverter");
+    add_archive(
+        &root,
+        "updates",
+        "Origin: Example\nLabel: Example\n",
+        &package("phased", "1.1", "Phased-Update-Percentage: 50\n"),
+    );
+
+    // there's no machine-id under the root, so apt doesn't phase, and 1.1 is the candidate
+    let mut cache = CacheBuilder::new().with_root(&root).build().unwrap();
+    assert_eq!(None, cache.machine_id());
+    let ids: Vec<String> = (0..64u32).map(|i| format!("{i:032x}")).collect();
+    let decided: Vec<(String, bool)> = {
+        let mut found = cache.find_by_name("phased");
+        let pkg = found.next().expect("from the root's status file");
+        assert_eq!(Some("1.1".to_string()), pkg.candidate_version());
+        let mut vers = pkg.versions();
+        let ver = loop {
+            let ver = vers.next().expect("the candidate is one of the versions");
+            if ver.version() == "1.1" {
+                break ver;
+            }
+        };
+        assert_eq!(Some(50), ver.phased_update_percentage());
+        assert!(ver.is_phased_in(&Phasing::Ignore));
+
+        ids.into_iter()
+            .map(|id| {
+                let phased_in = ver.is_phased_in(&Phasing::MachineId(id.clone()));
+                (id, phased_in)
+            })
+            .collect()
+    };
+
+    // about half are in; all of them are with an apt too old to phase
+    let inside = decided.iter().find(|(_, phased_in)| *phased_in);
+    let outside = decided.iter().find(|(_, phased_in)| !*phased_in);
+    assert!(inside.is_some());
+    for (id, phased_in) in inside.into_iter().chain(outside) {
+        let mut transaction = cache.transaction().unwrap();
+        let held_back = transaction
+            .upgrade(&Phasing::MachineId(id.clone()))
+            .unwrap();
+        assert_eq!(
+            !phased_in,
+            held_back.iter().any(|name| name.starts_with("phased:")),
+            "{held_back:?}"
+        );
+        assert_eq!(
+            *phased_in,
+            transaction
+                .changes()
+                .iter()
+                .any(|change| change.name == "phased" && change.action == Action::Upgrade)
+        );
+    }
+
+    let upgradable = cache.upgradable(&[]);
+    let phased = upgradable
+        .iter()
+        .find(|upgrade| upgrade.name == "phased")
+        .expect("1.1 is the candidate");
+    assert_eq!(Some(50), phased.phased_update_percentage);
+    assert!(
+        !phased.held_back,
+        "nothing is held back without a machine-id"
+    );
+
+    CacheBuilder::new().build().unwrap();
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn read_deb_file() {
+    use apt_pkg_native::debfile::DebFile;
+    use apt_pkg_native::debfile::MemberKind;
+
+    let path = build_test_deb("apt-pkg-native-debfile", "1.2-3");
+    let deb = DebFile::open(&path).unwrap();
+
+    assert_eq!(
+        Some("apt-pkg-native-debfile".to_string()),
+        deb.control_field("Package")
+    );
+    assert_eq!(Some("1.2-3".to_string()), deb.control_field("Version"));
+    assert_eq!(None, deb.control_field("Pre-Depends"));
+    assert!(deb.control().contains("Maintainer: Nobody"));
+
+    let postinst = deb.control_member("postinst").unwrap().unwrap();
+    assert_eq!(b"#!/bin/sh\nexit 0\n".to_vec(), postinst);
+    assert_eq!(None, deb.control_member("prerm").unwrap());
+
+    let members = deb.data_members().unwrap();
+    let binary = members
+        .iter()
+        .find(|m| m.path == "./usr/bin/apt-pkg-native-debfile")
+        .expect("binary is in the package");
+    assert_eq!(MemberKind::File, binary.kind);
+    assert_eq!(0o755, binary.mode);
+    assert_eq!(21, binary.size);
+    assert!(
+        members
+            .iter()
+            .any(|m| m.path == "./etc/" && m.kind == MemberKind::Directory)
+    );
+
+    assert_eq!(
+        Some(b"setting=1\n".to_vec()),
+        deb.data_member("/etc/apt-pkg-native-debfile.conf").unwrap()
+    );
+    assert_eq!(None, deb.data_member("/etc/missing").unwrap());
+
+    assert!(DebFile::open(path.with_extension("missing")).is_err());
+}
+
+#[test]
+fn deb_file_in_cache() {
+    use apt_pkg_native::transaction::Action;
+
+    let _rebuilding = rebuild_lock();
+    let name = "apt-pkg-native-local";
+    let path = build_test_deb(name, "0.1-1");
+    let mut cache = CacheBuilder::new().with_deb_file(&path).build().unwrap();
+
+    {
+        let mut found = cache.find_by_name(name);
+        let view = found.next().expect("local package is in the cache");
+        assert_eq!(
+            vec!["0.1-1".to_string()],
+            view.versions().map(|v| v.version()).collect::<Vec<_>>()
+        );
+    }
+
+    {
+        let mut transaction = cache.transaction().unwrap();
+        assert!(transaction.set_candidate(name, "0.1-1"));
+        assert!(transaction.mark_install(name));
+        transaction.resolve().unwrap();
+        assert_eq!(0, transaction.broken_count());
+
+        let changes = transaction.changes();
+        let change = changes
+            .iter()
+            .find(|c| c.name == name)
+            .expect("local package is installed");
+        assert_eq!(Action::Install, change.action);
+        assert_eq!(Some("0.1-1".to_string()), change.new_version);
+    }
+
+    assert!(
+        CacheBuilder::new()
+            .with_deb_file(path.with_extension("missing"))
+            .build()
+            .is_err()
+    );
+
+    CacheBuilder::new().build().unwrap();
+    assert!(cache.find_by_name(name).next().is_none());
+}
+
+#[cfg(not(feature = "ye-olde-apt"))]
+#[test]
+fn commit_dry_run() {
+    use apt_pkg_native::transaction::CommitMode;
+    use apt_pkg_native::transaction::EventKind;
+
+    let _rebuilding = rebuild_lock();
+    let name = "apt-pkg-native-committed";
+    let path = build_test_deb(name, "0.1-1");
+    let mut cache = CacheBuilder::new().with_deb_file(&path).build().unwrap();
+
+    let mut events = Vec::new();
+    {
+        let mut transaction = cache.transaction().unwrap();
+        assert!(transaction.mark_install(name));
+        transaction.resolve().unwrap();
+        transaction
+            .commit(CommitMode::DryRun, |event| events.push(event.clone()))
+            .unwrap();
+    }
+
+    let kinds: Vec<_> = events
+        .iter()
+        .filter(|event| event.package.starts_with(name))
+        .map(|event| event.kind)
+        .collect();
+    assert_eq!(vec![EventKind::Unpack, EventKind::Configure], kinds);
+    assert!(events.iter().all(|event| event.done <= event.total));
+    let last = events.last().unwrap();
+    assert_eq!(last.total, last.done);
+
+    {
+        let mut found = cache.find_by_name(name);
+        let view = found.next().expect("still in the cache");
+        assert_eq!(None, view.current_version(), "only simulated");
+    }
+
+    // a panic in the callback comes out of commit(), and it isn't called again
+    let mut calls = 0;
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut transaction = cache.transaction().unwrap();
+        assert!(transaction.mark_install(name));
+        transaction.resolve().unwrap();
+        let _ = transaction.commit(CommitMode::DryRun, |_| {
+            calls += 1;
+            panic!("from the callback");
+        });
+    }));
+    assert_eq!(
+        Some(&"from the callback"),
+        panicked.unwrap_err().downcast_ref::<&str>()
+    );
+    assert_eq!(1, calls);
+
+    CacheBuilder::new().build().unwrap();
+}
+
+#[test]
+fn architectures() {
+    let _rebuilding = rebuild_lock();
+    let cache = Cache::get_singleton();
+    let native = cache.native_arch();
+    assert!(!native.is_empty());
+    assert_eq!(Some(&native), cache.architectures().first());
+
+    let foreign = if native == "s390x" {
+        "mips64el"
+    } else {
+        "s390x"
+    };
+    let cache = CacheBuilder::new()
+        .with_architecture(foreign)
+        .build()
+        .unwrap();
+    assert_eq!(Some(&native), cache.architectures().first());
+    assert!(cache.architectures().contains(&foreign.to_string()));
+
+    let cache = CacheBuilder::new().build().unwrap();
+    assert!(!cache.architectures().contains(&foreign.to_string()));
+
+    // a build that fails mustn't leave the new architecture configured
+    let before = cache.architectures();
+    assert!(
+        CacheBuilder::new()
+            .with_architecture(foreign)
+            .with_deb_file("/nonexistent/foo.deb")
+            .build()
+            .is_err()
+    );
+    assert_eq!(before, cache.architectures());
+}